@@ -1,147 +1,859 @@
 //! The block moudle contains the implementation of 'block' in blockchain.
 
+use super::difficulty::{next_difficulty, validate_timestamp, DIFFICULTY_ADJUSTMENT_INTERVAL};
+use super::fork_choice::{ForkChoice, Reorg, ReorgEvent, MAX_REORG_EVENTS};
+use super::genesis::GenesisConfig;
+use super::ledger::account::AccountLedger;
+use super::ledger::{new_ledger, Ledger, LedgerMode};
+use super::merkle::{merkle_proof, merkle_root, MerkleProof};
+use super::store::memory::MemoryStore;
+use super::store::BlockStore;
+use super::transaction::{
+    transaction_leaves, transactions_root, validate_block_transactions, Transaction,
+};
 use chrono::Utc;
+use hex;
+use serde::{Deserialize, Deserializer, Serializer};
 use serde_json;
 use sha3::{Digest, Sha3_256};
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, ErrorKind};
 use std::mem::transmute;
 
+/// The block header, the block hash only covers the header.
+/// The transactions are committed by the merkle root.
 #[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct Block {
+pub struct BlockHeader {
     pub index: u32,
     pub time: String,
-    pub hash: Vec<u8>,
     pub pre_hash: Vec<u8>,
-    pub data: String,
+    /// Merkle root of the transactions.
+    pub merkle_root: Vec<u8>,
+    pub difficulty: u32,
+    pub nonce: u64,
+}
+
+impl BlockHeader {
+    /// Caclulate the hash of header fields.
+    /// Use sha3 - sha256 algorithm.
+    pub fn hash(&self) -> Vec<u8> {
+        let mut hasher = Sha3_256::default();
+        let index_byte: [u8; 4] = unsafe { transmute(self.index.to_le()) };
+        let difficulty_byte: [u8; 4] = unsafe { transmute(self.difficulty.to_le()) };
+        let nonce_byte: [u8; 8] = unsafe { transmute(self.nonce.to_le()) };
+        hasher.input(&index_byte);
+        hasher.input(self.time.as_bytes());
+        hasher.input(&self.pre_hash);
+        hasher.input(&self.merkle_root);
+        hasher.input(&difficulty_byte);
+        hasher.input(&nonce_byte);
+        hasher.result().as_slice().to_vec()
+    }
+}
+
+impl AsRef<BlockHeader> for BlockHeader {
+    fn as_ref(&self) -> &BlockHeader {
+        self
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct BlockBody {
+    pub transactions: Vec<Transaction>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Block {
+    pub hash: Vec<u8>,
+    pub header: BlockHeader,
+    pub body: BlockBody,
 }
 
 impl Block {
-    fn new(index: u32, time: String, hash: Vec<u8>, pre_hash: Vec<u8>, data: String) -> Self {
+    pub fn new(header: BlockHeader, body: BlockBody) -> Self {
         Block {
-            index,
-            time,
-            hash,
-            pre_hash,
-            data,
+            hash: header.hash(),
+            header,
+            body,
         }
     }
 
     pub fn to_vec(&self) -> Vec<u8> {
         serde_json::to_vec(self).unwrap()
     }
+
+    /// Build the merkle inclusion proof of the transaction with the hex encoded hash.
+    pub fn transaction_proof(&self, tx_hash: &str) -> Option<MerkleProof> {
+        let leaves = transaction_leaves(&self.body.transactions);
+        leaves
+            .iter()
+            .position(|leaf| hex::encode(leaf) == tx_hash)
+            .and_then(|i| merkle_proof(&leaves, i))
+    }
 }
 
-/// Caclulate the hash from input data.
-/// Use sha3 - sha256 algorithm.
-fn calculate_hash(index: u32, time: &str, pre_hash: &[u8], data: &str) -> Vec<u8> {
-    let mut hasher = Sha3_256::default();
-    let index_byte: [u8; 4] = unsafe { transmute(index.to_le()) };
-    hasher.input(&index_byte);
-    hasher.input(time.as_bytes());
-    hasher.input(pre_hash);
-    hasher.input(data.as_bytes());
-    hasher.result().as_slice().to_vec()
+impl AsRef<BlockHeader> for Block {
+    fn as_ref(&self) -> &BlockHeader {
+        &self.header
+    }
+}
+
+/// The difficulty of genesis block, i.e. the required leading zero bits of block hash.
+pub const INITIAL_DIFFICULTY: u32 = 12;
+
+/// Check if the hash starts with at least `difficulty` zero bits.
+pub fn hash_matches_difficulty(hash: &[u8], difficulty: u32) -> bool {
+    let mut zeros = 0;
+    for byte in hash {
+        if *byte == 0 {
+            zeros += 8;
+        } else {
+            zeros += byte.leading_zeros();
+            break;
+        }
+    }
+    zeros >= difficulty
+}
+
+/// Proof of work, increase the nonce until the header hash satisfies the difficulty.
+fn find_block(mut header: BlockHeader, body: BlockBody) -> Block {
+    loop {
+        let hash = header.hash();
+        if hash_matches_difficulty(&hash, header.difficulty) {
+            trace!("found block {} with nonce {}", header.index, header.nonce);
+            return Block { hash, header, body };
+        }
+        header.nonce += 1;
+    }
+}
+
+/// The order of listed blocks, by height.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlockOrder {
+    Asc,
+    Desc,
+}
+
+impl Default for BlockOrder {
+    fn default() -> Self {
+        BlockOrder::Asc
+    }
+}
+
+/// A page of blocks, the next cursor is the height where the next page starts.
+#[derive(Debug, Serialize)]
+pub struct BlocksPage<'a> {
+    pub tip: u32,
+    pub blocks: Vec<&'a Block>,
+    pub next_cursor: Option<u32>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct BlockChain {
-    chain: Vec<Block>,
+    /// Blocks are (de)serialized as a list, chains received from peers are kept in memory.
+    #[serde(
+        serialize_with = "serialize_store",
+        deserialize_with = "deserialize_store"
+    )]
+    chain: Box<BlockStore>,
+    /// The state derived from blocks, only maintained for own chain.
+    #[serde(skip, default = "empty_ledger")]
+    ledger: Box<Ledger>,
+    /// Heights of blocks keyed by block hash, only maintained for own chain.
+    /// Blocks are stored by height, so the height indexes the block in the store.
+    #[serde(skip)]
+    heights: HashMap<Vec<u8>, u32>,
+    /// Recently applied reorgs, the oldest first.
+    #[serde(skip)]
+    reorgs: VecDeque<ReorgEvent>,
+}
+
+fn serialize_store<S: Serializer>(
+    store: &Box<BlockStore>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(store.blocks())
+}
+
+fn deserialize_store<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Box<BlockStore>, D::Error> {
+    let blocks: Vec<Block> = Deserialize::deserialize(deserializer)?;
+    Ok(Box::new(MemoryStore::from(blocks)))
+}
+
+/// Placeholder ledger of chains received from peers.
+fn empty_ledger() -> Box<Ledger> {
+    Box::new(AccountLedger::default())
 }
 
 impl BlockChain {
-    /// Create a new block chain instance.
-    /// All blocks are stored into a vector.
-    pub fn new() -> Self {
-        let mut chain = Vec::new();
-        chain.push(BlockChain::generate_genesis_block());
-        BlockChain { chain }
-    }
-
-    fn generate_genesis_block() -> Block {
-        // Hardcoded hash and data of genesis block.
-        let time = format!("{}", Utc::now());
-        let pre_hash = Vec::<u8>::new();
-        let data = "Genesis block.".to_string();
-        let hash: Vec<u8> = vec![
-            58, 189, 197, 52, 175, 113, 254, 248, 138, 252, 216, 166, 7, 222, 247, 172, 174, 235,
-            214, 143, 214, 32, 59, 211, 64, 58, 123, 29, 148, 66, 54, 185,
-        ];
-        Block::new(0, time, hash, pre_hash, data)
+    /// Create a new block chain instance, with the ledger of the mode.
+    /// All blocks are stored into memory.
+    #[cfg(test)]
+    pub fn new(genesis: &GenesisConfig, ledger_mode: LedgerMode) -> Self {
+        BlockChain::open(genesis, ledger_mode, Box::new(MemoryStore::default()))
+            .expect("the memory store never fails.")
+    }
+
+    /// Open the chain held by the store, which must be of the same genesis block.
+    /// Stored blocks are validated and replayed to the ledger, an empty store is
+    /// initialized with the genesis block.
+    pub fn open(
+        genesis: &GenesisConfig,
+        ledger_mode: LedgerMode,
+        mut store: Box<BlockStore>,
+    ) -> io::Result<Self> {
+        let genesis_block = BlockChain::generate_genesis_block(genesis);
+        let mut ledger = new_ledger(ledger_mode, &genesis_block, &genesis.premine);
+        match store.blocks().first().map(|stored| stored.hash.clone()) {
+            Some(ref hash) if *hash != genesis_block.hash => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    "stored genesis block mismatch.",
+                ))
+            }
+            Some(_) => (),
+            None => store.append(genesis_block)?,
+        }
+        let mut heights = HashMap::new();
+        {
+            let blocks = store.blocks();
+            heights.insert(blocks[0].hash.clone(), 0);
+            for i in 1..blocks.len() {
+                if !validate_block(&blocks[i - 1], &blocks[i], next_difficulty(&blocks[..i]))
+                    || !ledger.apply_block(&blocks[i])
+                {
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        "stored block is invalid.",
+                    ));
+                }
+                heights.insert(blocks[i].hash.clone(), i as u32);
+            }
+        }
+        info!("loaded {} blocks from the store.", store.blocks().len());
+        Ok(BlockChain {
+            chain: store,
+            ledger,
+            heights,
+            reorgs: VecDeque::new(),
+        })
+    }
+
+    /// Generate the genesis block from config, the same config always results in the same block.
+    /// Genesis has no transactions, its merkle root commits to the whole config instead,
+    /// therefore different networks or allocations never share a genesis block.
+    fn generate_genesis_block(genesis: &GenesisConfig) -> Block {
+        let header = BlockHeader {
+            index: 0,
+            time: genesis.timestamp.clone(),
+            pre_hash: Vec::new(),
+            merkle_root: merkle_root(&[serde_json::to_vec(genesis).unwrap()]),
+            difficulty: genesis.initial_difficulty,
+            nonce: 0,
+        };
+        Block::new(header, BlockBody::default())
     }
 
     /// Generate next block, return a block.
-    pub fn generate_next_block<T: Into<String>>(&self, data: T) -> Block {
-        let pre_block = self.chain.last().unwrap();
-        let index = pre_block.index + 1;
-        let pre_hash = pre_block.hash.clone();
-        let time = format!("{}", Utc::now());
-        let data = data.into();
-        let hash = calculate_hash(index, &time, &pre_hash, &data);
-        Block::new(index, time, hash, pre_hash, data)
+    /// The block is mined with the retargeted difficulty of this chain.
+    pub fn generate_next_block(&self, transactions: Vec<Transaction>) -> Block {
+        let pre_block = self.chain.blocks().last().unwrap();
+        let header = BlockHeader {
+            index: pre_block.header.index + 1,
+            time: Utc::now().to_rfc3339(),
+            pre_hash: pre_block.hash.clone(),
+            merkle_root: transactions_root(&transactions),
+            difficulty: self.next_difficulty(),
+            nonce: 0,
+        };
+        find_block(header, BlockBody { transactions })
+    }
+
+    /// The difficulty required for the next block.
+    pub fn next_difficulty(&self) -> u32 {
+        next_difficulty(self.chain.blocks())
     }
 
+    /// Add a block to this chain, if the block is valid and its transactions
+    /// can be applied to the ledger, i.e. neither overspends nor replays.
     pub fn add_new_block(&mut self, block: Block) -> bool {
         // Get the latest block.
         let is_valid;
         {
-            let latest_block = self.chain.last().unwrap();
-            is_valid = validate_block(latest_block, &block)
+            let latest_block = self.chain.blocks().last().unwrap();
+            is_valid = validate_block(latest_block, &block, self.next_difficulty())
+                && self.ledger.apply_block(&block);
         }
-        if is_valid {
-            self.chain.push(block);
+        if !is_valid {
+            return false;
+        }
+        if let Err(err) = self.chain.append(block.clone()) {
+            error!("failed to store block #{}: {}", block.header.index, err);
+            self.ledger.rollback_block(&block);
+            return false;
         }
-        is_valid
+        self.heights.insert(block.hash, block.header.index);
+        true
+    }
+
+    pub fn ledger(&self) -> &Ledger {
+        &*self.ledger
     }
 
     pub fn len(&self) -> usize {
-        self.chain.len()
+        self.chain.blocks().len()
     }
 
     pub fn to_vec(&self) -> Vec<u8> {
         serde_json::to_vec(self).unwrap()
     }
 
+    pub fn genesis_hash(&self) -> &[u8] {
+        &self.chain.blocks()[0].hash
+    }
+
+    /// The headers of at most count blocks, starting from the index.
+    pub fn headers(&self, from: u32, count: u32) -> Vec<&BlockHeader> {
+        self.chain
+            .blocks()
+            .iter()
+            .skip(from as usize)
+            .take(count as usize)
+            .map(|block| &block.header)
+            .collect()
+    }
+
+    pub fn get_block(&self, index: u32) -> Option<&Block> {
+        self.chain.blocks().get(index as usize)
+    }
+
     pub fn get_latest(&self) -> Option<&Block> {
-        self.chain.last()
+        self.chain.blocks().last()
+    }
+
+    /// List at most limit blocks with heights between from and to inclusively, in the order.
+    /// The page starts at the cursor if given, otherwise at the first height in the order.
+    pub fn page(
+        &self,
+        from: u32,
+        to: u32,
+        cursor: Option<u32>,
+        limit: u32,
+        order: BlockOrder,
+    ) -> BlocksPage {
+        let tip = self.len() as u32 - 1;
+        let to = to.min(tip);
+        let limit = limit.max(1);
+        let mut page = BlocksPage {
+            tip,
+            blocks: Vec::new(),
+            next_cursor: None,
+        };
+        match order {
+            BlockOrder::Asc => {
+                let start = cursor.map_or(from, |cursor| cursor.max(from));
+                if start > to {
+                    return page;
+                }
+                let end = start.saturating_add(limit - 1).min(to);
+                page.blocks = self.chain.blocks()[start as usize..=end as usize]
+                    .iter()
+                    .collect();
+                if end < to {
+                    page.next_cursor = Some(end + 1);
+                }
+            }
+            BlockOrder::Desc => {
+                let start = cursor.map_or(to, |cursor| cursor.min(to));
+                if start < from {
+                    return page;
+                }
+                let end = start.saturating_sub(limit - 1).max(from);
+                page.blocks = self.chain.blocks()[end as usize..=start as usize]
+                    .iter()
+                    .rev()
+                    .collect();
+                if end > from {
+                    page.next_cursor = Some(end - 1);
+                }
+            }
+        }
+        page
+    }
+
+    /// The block of hash in this chain, None for unknown or stale fork blocks.
+    pub fn get_block_by_hash(&self, hash: &[u8]) -> Option<&Block> {
+        self.heights
+            .get(hash)
+            .and_then(|&height| self.get_block(height))
+    }
+
+    /// Number of the blocks following the block at ancestor index which are already in this chain.
+    fn known_blocks(&self, ancestor: usize, blocks: &[Block]) -> usize {
+        let own_blocks = self.chain.blocks().get(ancestor + 1..).unwrap_or(&[]);
+        blocks
+            .iter()
+            .zip(own_blocks.iter())
+            .take_while(|&(block, own_block)| block.hash == own_block.hash)
+            .count()
+    }
+
+    /// Validate the blocks following the block at ancestor index, as a fork of this chain.
+    /// Blocks are validated against the ancestor and the difficulty window before it,
+    /// so a short fork is checked without copying the whole chain.
+    pub fn validate_fork(&self, ancestor: u32, blocks: &[Block]) -> bool {
+        let chain = self.chain.blocks();
+        if ancestor as usize >= chain.len() {
+            return false;
+        }
+        let known = self.known_blocks(ancestor as usize, blocks);
+        let ancestor = ancestor as usize + known;
+
+        let window_start =
+            (ancestor + 1).saturating_sub(DIFFICULTY_ADJUSTMENT_INTERVAL as usize + 1);
+        let mut window: Vec<BlockHeader> = chain[window_start..ancestor + 1]
+            .iter()
+            .map(|block| block.header.clone())
+            .collect();
+        let mut pre_block = &chain[ancestor];
+        for block in blocks[known..].iter() {
+            if !validate_block(pre_block, block, next_difficulty(&window)) {
+                return false;
+            }
+            window.push(block.header.clone());
+            pre_block = block;
+        }
+        true
+    }
+
+    /// Return the reorg which should be applied if the fork following the block at ancestor index
+    /// is preferred by the fork choice. The fork should be validated by `validate_fork`.
+    pub fn preferred_reorg(
+        &self,
+        ancestor: u32,
+        mut blocks: Vec<Block>,
+        fork_choice: &ForkChoice,
+    ) -> Option<Reorg> {
+        let known = self.known_blocks(ancestor as usize, &blocks);
+        let blocks = blocks.split_off(known);
+        fork_choice.choose_fork(self.chain.blocks(), ancestor as usize + known, blocks)
+    }
+
+    /// Validate the fork following the block at ancestor index,
+    /// and return the reorg which should be applied if the fork is preferred by the fork choice.
+    pub fn reorg_to(
+        &self,
+        ancestor: u32,
+        blocks: Vec<Block>,
+        fork_choice: &ForkChoice,
+    ) -> Option<Reorg> {
+        if !self.validate_fork(ancestor, &blocks) {
+            return None;
+        }
+        self.preferred_reorg(ancestor, blocks, fork_choice)
+    }
+
+    /// Roll back blocks after the common ancestor and append the blocks of new fork.
+    /// The ledger is rolled back along with blocks, if any block of the new fork
+    /// can't be applied to the ledger or stored, restore the original chain and return false.
+    pub fn apply_reorg(&mut self, reorg: &Reorg) -> bool {
+        for block in reorg.rolled_back.iter().rev() {
+            self.ledger.rollback_block(block);
+        }
+        for (i, block) in reorg.applied.iter().enumerate() {
+            if !self.ledger.apply_block(block) {
+                debug!(
+                    "reorg failed: block #{} can't be applied to ledger.",
+                    block.header.index
+                );
+                self.restore_ledger(&reorg.applied[..i], &reorg.rolled_back);
+                return false;
+            }
+        }
+        if let Err(err) = self.store_fork(reorg.common_ancestor, &reorg.applied) {
+            error!("failed to store reorg: {}", err);
+            self.restore_ledger(&reorg.applied, &reorg.rolled_back);
+            if let Err(err) = self.store_fork(reorg.common_ancestor, &reorg.rolled_back) {
+                error!("failed to restore the original chain: {}", err);
+            }
+            return false;
+        }
+        for block in reorg.rolled_back.iter() {
+            self.heights.remove(&block.hash);
+        }
+        for block in self.chain.blocks()[reorg.common_ancestor as usize + 1..].iter() {
+            self.heights.insert(block.hash.clone(), block.header.index);
+        }
+        if self.reorgs.len() >= MAX_REORG_EVENTS {
+            self.reorgs.pop_front();
+        }
+        self.reorgs.push_back(ReorgEvent::from(reorg));
+        true
     }
 
-    pub fn pop_latest(&mut self) -> Option<Block> {
-        self.chain.pop()
+    /// Reorgs applied to this chain since it's opened, the oldest first.
+    pub fn recent_reorgs(&self) -> &VecDeque<ReorgEvent> {
+        &self.reorgs
+    }
+
+    /// Roll back the applied blocks of a failed reorg, and apply the rolled back ones again.
+    fn restore_ledger(&mut self, applied: &[Block], rolled_back: &[Block]) {
+        for block in applied.iter().rev() {
+            self.ledger.rollback_block(block);
+        }
+        for block in rolled_back.iter() {
+            self.ledger.apply_block(block);
+        }
+    }
+
+    /// Replace stored blocks after the common ancestor with the fork.
+    fn store_fork(&mut self, common_ancestor: u32, fork: &[Block]) -> io::Result<()> {
+        self.chain.truncate(common_ancestor as usize + 1)?;
+        for block in fork {
+            self.chain.append(block.clone())?;
+        }
+        Ok(())
     }
 }
 
 /// Validate the new generated block with this chain.
-pub fn validate_block(pre_block: &Block, block: &Block) -> bool {
-    let recalc_hash = calculate_hash(block.index, &block.time, &block.pre_hash, &block.data);
-    if pre_block.index + 1 != block.index {
+/// The difficulty is the one required by the chain for this block.
+pub fn validate_block(pre_block: &Block, block: &Block, difficulty: u32) -> bool {
+    let header = &block.header;
+    if !validate_header(&pre_block.header, header, &block.hash, difficulty) {
+        return false;
+    } else if transactions_root(&block.body.transactions) != header.merkle_root {
+        debug!("validation failed: invalid block merkle root.");
+        return false;
+    } else if !validate_block_transactions(&block.body.transactions, header.index) {
+        return false;
+    }
+    true
+}
+
+/// Validate the header with the previous header, without the block body.
+/// The hash is the claimed hash of the header.
+pub fn validate_header(
+    pre_header: &BlockHeader,
+    header: &BlockHeader,
+    hash: &[u8],
+    difficulty: u32,
+) -> bool {
+    if pre_header.index + 1 != header.index {
         debug!("validation failed: invalid block index.");
         return false;
-    } else if pre_block.hash != block.pre_hash {
+    } else if pre_header.hash() != header.pre_hash {
         debug!("validation failed: invalid block previous hash.");
         return false;
-    } else if recalc_hash != block.hash {
+    } else if header.hash() != hash {
         debug!("validation failed: invalid block hash.");
         return false;
+    } else if difficulty != header.difficulty {
+        debug!("validation failed: invalid block difficulty.");
+        return false;
+    } else if !hash_matches_difficulty(hash, header.difficulty) {
+        debug!("validation failed: block hash doesn't meet the difficulty.");
+        return false;
+    } else if !validate_timestamp(pre_header, header) {
+        debug!("validation failed: invalid block timestamp.");
+        return false;
     }
     true
 }
 
 /// Validate a block chain, iterate a blockchain and validate all blocks.
-fn validate_chain(block_chain: &BlockChain) -> bool {
+pub fn validate_chain(block_chain: &BlockChain) -> bool {
     // TODO: make the chain iterable.
-    let mut pre_block = block_chain.chain.iter().next().unwrap();
-    for next_block in block_chain.chain.iter() {
-        if !validate_block(pre_block, next_block) {
+    // The genesis block is not mined, skip it.
+    let chain = block_chain.chain.blocks();
+    for i in 1..chain.len() {
+        let difficulty = next_difficulty(&chain[..i]);
+        if !validate_block(&chain[i - 1], &chain[i], difficulty) {
             return false;
         }
-        pre_block = next_block;
     }
     true
 }
 
-pub fn replace_to_new_chain(old_chain: &BlockChain, new_chain: BlockChain) -> Option<BlockChain> {
-    if validate_chain(&new_chain) && new_chain.len() > old_chain.len() {
-        Some(new_chain)
+/// Replace to the new chain if it's valid and preferred by the fork choice.
+/// Return the reorg which should be applied to the old chain.
+pub fn replace_to_new_chain(
+    old_chain: &BlockChain,
+    new_chain: BlockChain,
+    fork_choice: &ForkChoice,
+) -> Option<Reorg> {
+    if validate_chain(&new_chain) {
+        fork_choice.choose(old_chain.chain.blocks(), new_chain.chain.blocks())
     } else {
         None
     }
 }
+
+#[cfg(test)]
+mod test_blockchain {
+    use super::super::merkle::verify_proof;
+    use super::*;
+    extern crate env_logger;
+    use ed25519_dalek::Keypair;
+    use rand::rngs::OsRng;
+
+    #[test]
+    fn test_mined_block_meets_difficulty() {
+        let _ = env_logger::try_init();
+        let mut block_chain = BlockChain::new(&GenesisConfig::default(), LedgerMode::Account);
+        let block = block_chain.generate_next_block(Vec::new());
+        assert!(hash_matches_difficulty(
+            &block.hash,
+            block.header.difficulty
+        ));
+        assert!(block_chain.add_new_block(block));
+        assert_eq!(2, block_chain.len());
+    }
+
+    #[test]
+    fn test_reject_tampered_nonce() {
+        let _ = env_logger::try_init();
+        let mut block_chain = BlockChain::new(&GenesisConfig::default(), LedgerMode::Account);
+        let mut block = block_chain.generate_next_block(Vec::new());
+        block.header.nonce += 1;
+        assert!(!block_chain.add_new_block(block));
+    }
+
+    #[test]
+    fn test_reject_forged_transaction() {
+        let _ = env_logger::try_init();
+        let mut block_chain = BlockChain::new(&GenesisConfig::default(), LedgerMode::Account);
+        let keypair = Keypair::generate(&mut OsRng);
+        let mut tx = Transaction::new_signed(&keypair, "recipient", 10, 1, 0);
+        tx.amount = 100;
+        let block = block_chain.generate_next_block(vec![tx]);
+        assert!(!block_chain.add_new_block(block));
+    }
+
+    #[test]
+    fn test_reject_tampered_body() {
+        let _ = env_logger::try_init();
+        let mut block_chain = BlockChain::new(&GenesisConfig::default(), LedgerMode::Account);
+        let keypair = Keypair::generate(&mut OsRng);
+        let mut block = block_chain.generate_next_block(Vec::new());
+        // The header is untouched, but the body no longer matches the merkle root.
+        block.body.transactions = vec![Transaction::new_signed(&keypair, "recipient", 10, 1, 0)];
+        assert_eq!(block.hash, block.header.hash());
+        assert!(!block_chain.add_new_block(block));
+    }
+
+    #[test]
+    fn test_transaction_proof() {
+        let block_chain = BlockChain::new(&GenesisConfig::default(), LedgerMode::Account);
+        let keypair = Keypair::generate(&mut OsRng);
+        let transactions: Vec<Transaction> = (0..3)
+            .map(|nonce| Transaction::new_signed(&keypair, "recipient", 10, 1, nonce))
+            .collect();
+        let block = block_chain.generate_next_block(transactions.clone());
+        for tx in transactions.iter() {
+            let proof = block.transaction_proof(&hex::encode(tx.hash())).unwrap();
+            assert!(verify_proof(&tx.hash(), &proof, &block.header.merkle_root));
+        }
+        assert!(block.transaction_proof("unknown").is_none());
+    }
+
+    #[test]
+    fn test_hash_matches_difficulty() {
+        assert!(hash_matches_difficulty(&[0, 0x0f, 0xff], 12));
+        assert!(!hash_matches_difficulty(&[0, 0x1f, 0xff], 12));
+    }
+
+    #[test]
+    fn test_deterministic_genesis() {
+        let genesis = GenesisConfig::default();
+        let block_chain = BlockChain::new(&genesis, LedgerMode::Account);
+        assert_eq!(
+            block_chain.genesis_hash(),
+            BlockChain::new(&genesis, LedgerMode::Account).genesis_hash()
+        );
+
+        let mut other_network = GenesisConfig::default();
+        other_network.network_id = "other".to_string();
+        assert!(
+            block_chain.genesis_hash()
+                != BlockChain::new(&other_network, LedgerMode::Account).genesis_hash()
+        );
+    }
+
+    #[test]
+    fn test_reload_from_store() {
+        use super::super::store::file::FileStore;
+        use std::{env, fs};
+
+        let dir =
+            env::temp_dir().join(format!("bam-chain-reload-{}", Utc::now().timestamp_nanos()));
+        let genesis = GenesisConfig::default();
+        let store = FileStore::open(&dir).unwrap();
+        let mut block_chain =
+            BlockChain::open(&genesis, LedgerMode::Account, Box::new(store)).unwrap();
+        for _ in 0..2 {
+            let block = block_chain.generate_next_block(Vec::new());
+            assert!(block_chain.add_new_block(block));
+        }
+        drop(block_chain);
+
+        let store = FileStore::open(&dir).unwrap();
+        let reloaded = BlockChain::open(&genesis, LedgerMode::Account, Box::new(store)).unwrap();
+        assert_eq!(3, reloaded.len());
+
+        let mut other_network = GenesisConfig::default();
+        other_network.network_id = "other".to_string();
+        let store = FileStore::open(&dir).unwrap();
+        assert!(BlockChain::open(&other_network, LedgerMode::Account, Box::new(store)).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_serialize_as_block_list() {
+        let mut block_chain = BlockChain::open(
+            &GenesisConfig::default(),
+            LedgerMode::Account,
+            Box::new(MemoryStore::default()),
+        )
+        .unwrap();
+        let block = block_chain.generate_next_block(Vec::new());
+        assert!(block_chain.add_new_block(block));
+
+        let value: serde_json::Value = serde_json::from_slice(&block_chain.to_vec()).unwrap();
+        assert_eq!(2, value["chain"].as_array().unwrap().len());
+        let received: BlockChain = serde_json::from_slice(&block_chain.to_vec()).unwrap();
+        assert!(validate_chain(&received));
+        assert_eq!(
+            block_chain.get_latest().unwrap().hash,
+            received.get_latest().unwrap().hash
+        );
+    }
+
+    #[test]
+    fn test_index_blocks_by_hash() {
+        let genesis = GenesisConfig::default();
+        let mut block_chain = BlockChain::new(&genesis, LedgerMode::Account);
+        let mut fork = BlockChain::new(&genesis, LedgerMode::Account);
+        let stale = block_chain.generate_next_block(Vec::new());
+        assert!(block_chain.add_new_block(stale.clone()));
+        assert_eq!(
+            1,
+            block_chain
+                .get_block_by_hash(&stale.hash)
+                .unwrap()
+                .header
+                .index
+        );
+
+        let mut blocks = Vec::new();
+        for _ in 0..2 {
+            let block = fork.generate_next_block(Vec::new());
+            assert!(fork.add_new_block(block.clone()));
+            blocks.push(block);
+        }
+        let reorg = block_chain
+            .reorg_to(0, blocks.clone(), &ForkChoice::default())
+            .unwrap();
+        assert!(block_chain.apply_reorg(&reorg));
+        assert!(block_chain.get_block_by_hash(&stale.hash).is_none());
+        assert_eq!(
+            2,
+            block_chain
+                .get_block_by_hash(&blocks[1].hash)
+                .unwrap()
+                .header
+                .index
+        );
+        assert!(block_chain
+            .get_block_by_hash(block_chain.genesis_hash())
+            .is_some());
+    }
+
+    /// A memory store failing to append beyond the max length.
+    #[derive(Debug)]
+    struct BoundedStore {
+        store: MemoryStore,
+        max_len: usize,
+    }
+
+    impl BlockStore for BoundedStore {
+        fn blocks(&self) -> &[Block] {
+            self.store.blocks()
+        }
+
+        fn append(&mut self, block: Block) -> io::Result<()> {
+            if self.store.blocks().len() >= self.max_len {
+                return Err(io::Error::new(ErrorKind::Other, "store is full."));
+            }
+            self.store.append(block)
+        }
+
+        fn truncate(&mut self, len: usize) -> io::Result<()> {
+            self.store.truncate(len)
+        }
+    }
+
+    #[test]
+    fn test_restore_chain_on_store_failure() {
+        let genesis = GenesisConfig::default();
+        let store = BoundedStore {
+            store: MemoryStore::default(),
+            max_len: 2,
+        };
+        let mut block_chain =
+            BlockChain::open(&genesis, LedgerMode::Account, Box::new(store)).unwrap();
+        let stale = block_chain.generate_next_block(vec![Transaction::coinbase("miner", 1, 1)]);
+        assert!(block_chain.add_new_block(stale.clone()));
+
+        let mut fork = BlockChain::new(&genesis, LedgerMode::Account);
+        let mut blocks = Vec::new();
+        for index in 1..3 {
+            let block = fork.generate_next_block(vec![Transaction::coinbase("fork", 1, index)]);
+            assert!(fork.add_new_block(block.clone()));
+            blocks.push(block);
+        }
+        let reorg = block_chain
+            .reorg_to(0, blocks.clone(), &ForkChoice::default())
+            .unwrap();
+        assert!(!block_chain.apply_reorg(&reorg));
+        assert_eq!(stale.hash, block_chain.get_latest().unwrap().hash);
+        assert!(block_chain.get_block_by_hash(&blocks[0].hash).is_none());
+        assert_eq!(1, block_chain.ledger().balance("miner"));
+        assert_eq!(0, block_chain.ledger().balance("fork"));
+    }
+
+    #[test]
+    fn test_page_blocks() {
+        let mut block_chain = BlockChain::new(&GenesisConfig::default(), LedgerMode::Account);
+        for _ in 0..5 {
+            let block = block_chain.generate_next_block(Vec::new());
+            assert!(block_chain.add_new_block(block));
+        }
+        let heights = |page: &BlocksPage| -> Vec<u32> {
+            page.blocks.iter().map(|block| block.header.index).collect()
+        };
+
+        let page = block_chain.page(1, 100, None, 2, BlockOrder::Asc);
+        assert_eq!(5, page.tip);
+        assert_eq!(vec![1, 2], heights(&page));
+        let page = block_chain.page(1, 100, page.next_cursor, 2, BlockOrder::Asc);
+        assert_eq!(vec![3, 4], heights(&page));
+        let page = block_chain.page(1, 100, page.next_cursor, 2, BlockOrder::Asc);
+        assert_eq!(vec![5], heights(&page));
+        assert_eq!(None, page.next_cursor);
+
+        let page = block_chain.page(0, 4, None, 3, BlockOrder::Desc);
+        assert_eq!(vec![4, 3, 2], heights(&page));
+        let page = block_chain.page(0, 4, page.next_cursor, 3, BlockOrder::Desc);
+        assert_eq!(vec![1, 0], heights(&page));
+        assert_eq!(None, page.next_cursor);
+
+        assert!(block_chain
+            .page(6, 10, None, 3, BlockOrder::Asc)
+            .blocks
+            .is_empty());
+    }
+}
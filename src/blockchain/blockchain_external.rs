@@ -1,27 +1,69 @@
 //! This module builds mining relative external routes for user interactions.
 
-use super::blockchain::BlockChain;
-use super::blockchain_sync::SyncBlockMessage;
+use super::blockchain::{Block, BlockChain};
+use super::blockchain_sync::{accept_and_relay_transaction, publish_blocks};
+use super::headers_sync::MAX_HEADERS_PER_REQUEST;
+use super::mempool::Mempool;
+use super::merkle::MerkleProof;
+use super::transaction::{Transaction, BLOCK_REWARD};
+use config::NODE_CONFIG;
+use extractors::{AddressPath, BlocksQuery, HashPath, HeadersQuery, IndexPath, ProofPath};
 use futures::{future, Future, Stream};
 use gotham::handler::{HandlerFuture, IntoHandlerError};
 use gotham::http::response::create_response;
 use gotham::state::{FromState, State};
+use hex;
 use hyper::{Body, Response, StatusCode};
 use mime::{APPLICATION_JSON, TEXT_PLAIN};
 use peers::PeerAPIs;
+use serde_json;
 use std::io::{self, ErrorKind};
 use std::sync::{Arc, RwLock};
 use tokio_core::reactor::Handle;
 
+/// Number of blocks in a page, if the limit is absent.
+pub const DEFAULT_BLOCKS_PER_PAGE: u32 = 20;
+
+/// Maximum number of blocks in a page.
+pub const MAX_BLOCKS_PER_PAGE: u32 = 100;
+
+#[derive(Serialize)]
+struct BalanceResponse {
+    address: String,
+    balance: u64,
+}
+
+#[derive(Serialize)]
+struct AccountResponse {
+    address: String,
+    balance: u64,
+    nonce: u64,
+}
+
+#[derive(Serialize)]
+struct ProofResponse {
+    block_index: u32,
+    block_hash: String,
+    merkle_root: String,
+    tx_hash: String,
+    proof: MerkleProof,
+}
+
 pub struct BlockChainExternalAPIs {
     block_chain: Arc<RwLock<BlockChain>>,
+    mempool: Arc<RwLock<Mempool>>,
     peer_apis: Arc<PeerAPIs>,
 }
 
 impl BlockChainExternalAPIs {
-    pub fn new(block_chain: Arc<RwLock<BlockChain>>, peer_apis: Arc<PeerAPIs>) -> Self {
+    pub fn new(
+        block_chain: Arc<RwLock<BlockChain>>,
+        mempool: Arc<RwLock<Mempool>>,
+        peer_apis: Arc<PeerAPIs>,
+    ) -> Self {
         BlockChainExternalAPIs {
             block_chain,
+            mempool,
             peer_apis,
         }
     }
@@ -36,45 +78,272 @@ impl BlockChainExternalAPIs {
         (state, res)
     }
 
-    /// Use the request data to generate new block.
+    /// List recently applied reorgs, the oldest first.
+    pub fn list_reorgs(&self, state: State) -> (State, Response) {
+        let body = serde_json::to_vec(self.block_chain.read().unwrap().recent_reorgs()).unwrap();
+        let res = create_response(&state, StatusCode::Ok, Some((body, APPLICATION_JSON)));
+        (state, res)
+    }
+
+    /// Listing a page of blocks, at most `MAX_BLOCKS_PER_PAGE` blocks are responsed.
+    /// Blocks are listed from the genesis block to the tip if the range is absent.
+    pub fn list_blocks(&self, mut state: State) -> (State, Response) {
+        let BlocksQuery {
+            from,
+            to,
+            limit,
+            order,
+            cursor,
+        } = BlocksQuery::take_from(&mut state);
+        let limit = limit.map_or(DEFAULT_BLOCKS_PER_PAGE, |limit| {
+            limit.min(MAX_BLOCKS_PER_PAGE)
+        });
+        let body = serde_json::to_vec(&self.block_chain.read().unwrap().page(
+            from.unwrap_or(0),
+            to.unwrap_or(u32::max_value()),
+            cursor,
+            limit,
+            order.unwrap_or_default(),
+        ))
+        .unwrap();
+        let res = create_response(&state, StatusCode::Ok, Some((body, APPLICATION_JSON)));
+        (state, res)
+    }
+
+    /// Listing headers of the block chain, without block bodies.
+    /// At most `MAX_HEADERS_PER_REQUEST` headers are responsed, starting from the index.
+    pub fn get_headers(&self, mut state: State) -> (State, Response) {
+        let HeadersQuery { from, count } = HeadersQuery::take_from(&mut state);
+        let count = count.map_or(MAX_HEADERS_PER_REQUEST, |count| {
+            count.min(MAX_HEADERS_PER_REQUEST)
+        });
+        let body = serde_json::to_vec(
+            &self
+                .block_chain
+                .read()
+                .unwrap()
+                .headers(from.unwrap_or(0), count),
+        )
+        .unwrap();
+        let res = create_response(&state, StatusCode::Ok, Some((body, APPLICATION_JSON)));
+        (state, res)
+    }
+
+    /// Response the header of the block at index in path.
+    /// Response NotFound if the block doesn't exist.
+    pub fn get_header(&self, mut state: State) -> (State, Response) {
+        let IndexPath { index } = IndexPath::take_from(&mut state);
+        let header = self
+            .block_chain
+            .read()
+            .unwrap()
+            .get_block(index)
+            .map(|block| serde_json::to_vec(&block.header).unwrap());
+        let res = match header {
+            Some(body) => create_response(&state, StatusCode::Ok, Some((body, APPLICATION_JSON))),
+            None => create_response(&state, StatusCode::NotFound, None),
+        };
+        (state, res)
+    }
+
+    /// Response the body of the block at index in path.
+    /// Response NotFound if the block doesn't exist.
+    pub fn get_body(&self, mut state: State) -> (State, Response) {
+        let IndexPath { index } = IndexPath::take_from(&mut state);
+        let body = self
+            .block_chain
+            .read()
+            .unwrap()
+            .get_block(index)
+            .map(|block| serde_json::to_vec(&block.body).unwrap());
+        let res = match body {
+            Some(body) => create_response(&state, StatusCode::Ok, Some((body, APPLICATION_JSON))),
+            None => create_response(&state, StatusCode::NotFound, None),
+        };
+        (state, res)
+    }
+
+    /// Response the block of hash in path.
+    /// Response NotFound if the block isn't in own chain, or the hash isn't hex encoded.
+    pub fn get_block_by_hash(&self, mut state: State) -> (State, Response) {
+        let HashPath { hash } = HashPath::take_from(&mut state);
+        let block = hex::decode(&hash).ok().and_then(|hash| {
+            self.block_chain
+                .read()
+                .unwrap()
+                .get_block_by_hash(&hash)
+                .map(Block::to_vec)
+        });
+        let res = match block {
+            Some(body) => create_response(&state, StatusCode::Ok, Some((body, APPLICATION_JSON))),
+            None => create_response(&state, StatusCode::NotFound, None),
+        };
+        (state, res)
+    }
+
+    /// Response the block at height in path.
+    /// Response NotFound if the block doesn't exist.
+    pub fn get_block_by_height(&self, mut state: State) -> (State, Response) {
+        let IndexPath { index } = IndexPath::take_from(&mut state);
+        let block = self
+            .block_chain
+            .read()
+            .unwrap()
+            .get_block(index)
+            .map(Block::to_vec);
+        let res = match block {
+            Some(body) => create_response(&state, StatusCode::Ok, Some((body, APPLICATION_JSON))),
+            None => create_response(&state, StatusCode::NotFound, None),
+        };
+        (state, res)
+    }
+
+    /// Response the balance of the address in path.
+    pub fn get_balance(&self, mut state: State) -> (State, Response) {
+        let AddressPath { address } = AddressPath::take_from(&mut state);
+        let balance = self.block_chain.read().unwrap().ledger().balance(&address);
+        let body = serde_json::to_vec(&BalanceResponse { address, balance }).unwrap();
+        let res = create_response(&state, StatusCode::Ok, Some((body, APPLICATION_JSON)));
+        (state, res)
+    }
+
+    /// Response the balance and the next nonce of the address in path.
+    /// Response NotFound if the ledger doesn't use the account model.
+    pub fn get_account(&self, mut state: State) -> (State, Response) {
+        let AddressPath { address } = AddressPath::take_from(&mut state);
+        let account = self.block_chain.read().unwrap().ledger().account(&address);
+        let res = match account {
+            Some(account) => {
+                let body = serde_json::to_vec(&AccountResponse {
+                    address,
+                    balance: account.balance,
+                    nonce: account.nonce,
+                })
+                .unwrap();
+                create_response(&state, StatusCode::Ok, Some((body, APPLICATION_JSON)))
+            }
+            None => create_response(&state, StatusCode::NotFound, None),
+        };
+        (state, res)
+    }
+
+    /// Response the unspent outputs of the address in path.
+    /// Response NotFound if the ledger doesn't use the utxo model.
+    pub fn get_unspent_outputs(&self, mut state: State) -> (State, Response) {
+        let AddressPath { address } = AddressPath::take_from(&mut state);
+        let utxos = self
+            .block_chain
+            .read()
+            .unwrap()
+            .ledger()
+            .unspent_outputs(&address);
+        let res = match utxos {
+            Some(utxos) => create_response(
+                &state,
+                StatusCode::Ok,
+                Some((serde_json::to_vec(&utxos).unwrap(), APPLICATION_JSON)),
+            ),
+            None => create_response(&state, StatusCode::NotFound, None),
+        };
+        (state, res)
+    }
+
+    /// Response the merkle inclusion proof of the transaction in the block.
+    /// Response NotFound if the block or the transaction doesn't exist.
+    pub fn get_transaction_proof(&self, mut state: State) -> (State, Response) {
+        let ProofPath { block, tx } = ProofPath::take_from(&mut state);
+        let proof = self
+            .block_chain
+            .read()
+            .unwrap()
+            .get_block(block)
+            .and_then(|b| {
+                b.transaction_proof(&tx).map(|proof| ProofResponse {
+                    block_index: b.header.index,
+                    block_hash: hex::encode(&b.hash),
+                    merkle_root: hex::encode(&b.header.merkle_root),
+                    tx_hash: tx.clone(),
+                    proof,
+                })
+            });
+        let res = match proof {
+            Some(proof) => create_response(
+                &state,
+                StatusCode::Ok,
+                Some((serde_json::to_vec(&proof).unwrap(), APPLICATION_JSON)),
+            ),
+            None => create_response(&state, StatusCode::NotFound, None),
+        };
+        (state, res)
+    }
+
+    /// Validate the posted transaction against current state, add it into mempool,
+    /// and relay it to peers.
+    /// Response BadRequest if the transaction is malformed, invalid or duplicated.
+    pub fn submit_transaction(&self, mut state: State) -> Box<HandlerFuture> {
+        let handle = Handle::borrow_from(&mut state).clone();
+        let cloned_chain = self.block_chain.clone();
+        let cloned_mempool = self.mempool.clone();
+        let cloned_peer_apis = self.peer_apis.clone();
+        let relay_future = Body::take_from(&mut state).concat2().and_then(move |body| {
+            accept_and_relay_transaction(
+                &cloned_chain,
+                &cloned_mempool,
+                &cloned_peer_apis,
+                handle,
+                body.to_vec(),
+            )
+        });
+
+        Box::new(relay_future.then(move |result| match result {
+            Ok(accepted) => {
+                let status = if accepted {
+                    StatusCode::Ok
+                } else {
+                    StatusCode::BadRequest
+                };
+                let res = create_response(&state, status, None);
+                Ok((state, res))
+            }
+            Err(err) => Err((state, err.into_handler_error())),
+        }))
+    }
+
+    /// Mine a new block with pending transactions.
     /// Currently, the workflow will be:
-    /// 1. Parse the requests body, whether the format is.
-    /// 2. Generate a new block from the request data.
+    /// 1. Select transactions from mempool by fee, up to the block size limit,
+    ///    prepend the coinbase if the miner address is configured.
+    /// 2. Generate a new block from the transactions, i.e. proof of work.
     /// 3. Checkout if the request new block is validate.
-    /// 4. Add block to chain if validate.
-    /// 5. Generate broadcast requests as concatenate futures.
+    /// 4. Add block to chain if validate, and re-validate the mempool.
+    /// 5. Publish the new block to peers, rather than the whole chain.
     /// 6. Response the updated block chain.
-    ///
-    /// The following minining is currently without POW, POS works.
-    /// Will investigate after whole communication go done.
     pub fn mine_block(&self, mut state: State) -> Box<HandlerFuture> {
         let cloned_chain_parse = self.block_chain.clone();
-        let parse_future = Body::take_from(&mut state).concat2().and_then(move |body| {
-            let content = String::from_utf8(body.to_vec()).unwrap();
-            let new_block = cloned_chain_parse
-                .read()
-                .unwrap()
-                .generate_next_block(content);
-            let valid = cloned_chain_parse
-                .write()
-                .unwrap()
-                .add_new_block(new_block.clone());
-            if !valid {
+        let cloned_mempool = self.mempool.clone();
+        let parse_future = Body::take_from(&mut state).concat2().and_then(move |_| {
+            let new_block = {
+                let own_chain = cloned_chain_parse.read().unwrap();
+                let transactions = cloned_mempool.read().unwrap().select(own_chain.ledger());
+                let transactions = with_coinbase(&own_chain, transactions);
+                own_chain.generate_next_block(transactions)
+            };
+            let mut own_chain = cloned_chain_parse.write().unwrap();
+            if !own_chain.add_new_block(new_block.clone()) {
                 return future::err(io::Error::new(ErrorKind::Other, "invalid block.").into());
             }
+            cloned_mempool
+                .write()
+                .unwrap()
+                .revalidate(own_chain.ledger());
             future::ok(new_block)
         });
 
         let handle = Handle::borrow_from(&mut state).clone();
-        let cloned_chain_notify = self.block_chain.clone();
         let cloned_peer_apis = self.peer_apis.clone();
         let notify_future = parse_future.and_then(move |b| {
             trace!("start broadcasting after parsing block...");
-            let own_chain = cloned_chain_notify.read().unwrap();
-            let broadcast_future = cloned_peer_apis.broadcast(
-                handle,
-                SyncBlockMessage::PublishLatestBlock(own_chain.to_vec()),
-            );
+            let broadcast_future = publish_blocks(&cloned_peer_apis, handle, vec![b]);
             broadcast_future.and_then(|_| Ok(()))
         });
 
@@ -93,3 +362,24 @@ impl BlockChainExternalAPIs {
         }))
     }
 }
+
+/// Prepend the coinbase transaction claiming the block reward and fees to the miner address.
+fn with_coinbase(block_chain: &BlockChain, transactions: Vec<Transaction>) -> Vec<Transaction> {
+    match NODE_CONFIG.miner_address {
+        Some(ref miner_address) => {
+            let fees = transactions
+                .iter()
+                .fold(0u64, |sum, tx| sum.saturating_add(tx.fee));
+            let index = block_chain.get_latest().unwrap().header.index + 1;
+            let coinbase = Transaction::coinbase(
+                miner_address.clone(),
+                BLOCK_REWARD.saturating_add(fees),
+                index,
+            );
+            let mut with_coinbase = vec![coinbase];
+            with_coinbase.extend(transactions);
+            with_coinbase
+        }
+        None => transactions,
+    }
+}
@@ -1,39 +1,264 @@
 //! This module builds mining relative internal routes for nodes handshakes.
 
-use super::blockchain::{replace_to_new_chain, BlockChain};
+use super::blockchain::{Block, BlockChain};
+use super::fork_choice::{ForkChoice, Reorg};
+use super::headers_sync::HeadersSync;
+use super::mempool::Mempool;
+use super::orphan::OrphanPool;
+use super::pull_sync::{PullSync, DEFAULT_PULL_SYNC_INTERVAL};
+use super::transaction::Transaction;
+use config::NODE_CONFIG;
 use futures::{future, Future, Stream};
 use gotham::handler::{HandlerFuture, IntoHandlerError};
 use gotham::http::response::create_response;
-use gotham::state::{FromState, State};
-use hyper::{Body, Response, StatusCode};
+use gotham::state::{client_addr, FromState, State};
+use hyper::{self, Body, Response, StatusCode};
 use mime::APPLICATION_JSON;
-use peers::PeerAPIs;
+use peers::ban::Misbehavior;
+use peers::{peer_address, BroadcastFuture, PeerAPIs, LISTENED_PORT};
 use serde_json;
 use std::io::{self, ErrorKind};
-use std::mem;
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
 use tokio_core::reactor::Handle;
 
 #[derive(Serialize, Deserialize)]
 pub enum SyncBlockMessage {
     QueryLatest,
     QueryAll,
-    PublishLatestBlock(Vec<u8>),
+    PublishBlocks(Vec<u8>),
+    PublishTransaction(Vec<u8>),
+}
+
+/// Maximum number of blocks published at once, the receiver requests the rest if needed.
+pub const MAX_PUBLISH_BLOCKS: usize = 16;
+
+/// Blocks published to peers, with the listening port of the sender,
+/// where the receiver requests missing ancestors from.
+#[derive(Serialize, Deserialize)]
+pub struct BlockAnnouncement {
+    pub port: u32,
+    pub blocks: Vec<Block>,
+}
+
+impl BlockAnnouncement {
+    /// Announce the last blocks, at most `MAX_PUBLISH_BLOCKS`.
+    pub fn new(mut blocks: Vec<Block>) -> Self {
+        let skipped = blocks.len().saturating_sub(MAX_PUBLISH_BLOCKS);
+        BlockAnnouncement {
+            port: *LISTENED_PORT,
+            blocks: blocks.split_off(skipped),
+        }
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        serde_json::to_vec(self).unwrap()
+    }
+}
+
+/// Broadcast the blocks to all peers.
+pub fn publish_blocks(peer_apis: &PeerAPIs, handle: Handle, blocks: Vec<Block>) -> BroadcastFuture {
+    let announcement = BlockAnnouncement::new(blocks);
+    peer_apis.broadcast(
+        handle,
+        SyncBlockMessage::PublishBlocks(announcement.to_vec()),
+    )
+}
+
+#[derive(Debug)]
+pub enum PublishOutcome {
+    /// Blocks added to own chain or switched to, which should be relayed.
+    Accepted(Vec<Block>),
+    /// Blocks are known or not preferred.
+    Ignored,
+    /// Blocks extending own chain or forking from it fail the validation.
+    Invalid,
+    /// The first block doesn't connect to own chain.
+    MissingAncestors,
+}
+
+/// Add the published blocks into own chain, or switch to them if they make a preferred fork.
+pub fn accept_published_blocks(
+    own_chain: &mut BlockChain,
+    mempool: &RwLock<Mempool>,
+    fork_choice: &ForkChoice,
+    mut blocks: Vec<Block>,
+) -> PublishOutcome {
+    blocks.sort_by_key(|block| block.header.index);
+    let connected = match blocks.first() {
+        Some(first) if first.header.index > 0 => own_chain
+            .get_block(first.header.index - 1)
+            .map_or(false, |block| block.hash == first.header.pre_hash),
+        _ => return PublishOutcome::Ignored,
+    };
+    if !connected {
+        return PublishOutcome::MissingAncestors;
+    }
+    // Skip blocks already in own chain.
+    let known = blocks
+        .iter()
+        .take_while(|block| {
+            own_chain
+                .get_block(block.header.index)
+                .map_or(false, |own_block| own_block.hash == block.hash)
+        })
+        .count();
+    let blocks = blocks.split_off(known);
+    if blocks.is_empty() {
+        return PublishOutcome::Ignored;
+    }
+
+    let ancestor = blocks[0].header.index - 1;
+    if ancestor == own_chain.get_latest().unwrap().header.index {
+        trace!("published blocks extend own chain, add them ...");
+        let mut accepted = Vec::new();
+        for block in blocks {
+            if !own_chain.add_new_block(block.clone()) {
+                break;
+            }
+            accepted.push(block);
+        }
+        mempool.write().unwrap().revalidate(own_chain.ledger());
+        if accepted.is_empty() {
+            return PublishOutcome::Invalid;
+        }
+        return PublishOutcome::Accepted(accepted);
+    }
+    if !own_chain.validate_fork(ancestor, &blocks) {
+        return PublishOutcome::Invalid;
+    }
+    match own_chain.preferred_reorg(ancestor, blocks, fork_choice) {
+        Some(ref reorg) if reorganize(own_chain, mempool, reorg) => {
+            PublishOutcome::Accepted(reorg.applied.clone())
+        }
+        Some(_) => PublishOutcome::Invalid,
+        None => PublishOutcome::Ignored,
+    }
+}
+
+/// Connect orphans whose ancestors have arrived, following their children as far as possible.
+/// Return blocks accepted into own chain.
+pub fn connect_orphans(
+    own_chain: &mut BlockChain,
+    mempool: &RwLock<Mempool>,
+    fork_choice: &ForkChoice,
+    orphans: &mut OrphanPool,
+) -> Vec<Block> {
+    let mut accepted = Vec::new();
+    loop {
+        let roots = orphans.take_connectable(own_chain);
+        if roots.is_empty() {
+            return accepted;
+        }
+        for root in roots {
+            let mut branch = vec![root];
+            while let Some(child) = orphans.take_child(&branch.last().unwrap().hash) {
+                branch.push(child);
+            }
+            if let PublishOutcome::Accepted(blocks) =
+                accept_published_blocks(own_chain, mempool, fork_choice, branch)
+            {
+                accepted.extend(blocks);
+            }
+        }
+    }
+}
+
+/// Accept the serialized transaction into mempool if it's never seen,
+/// and relay it to peers once accepted.
+/// Resolve to whether the transaction is accepted.
+pub fn accept_and_relay_transaction(
+    block_chain: &Arc<RwLock<BlockChain>>,
+    mempool: &Arc<RwLock<Mempool>>,
+    peer_apis: &Arc<PeerAPIs>,
+    handle: Handle,
+    content: Vec<u8>,
+) -> Box<Future<Item = bool, Error = hyper::Error>> {
+    let tx: Transaction = match serde_json::from_slice(&content) {
+        Ok(tx) => tx,
+        Err(_) => {
+            trace!("parse the requested transaction failed.");
+            return Box::new(future::ok(false));
+        }
+    };
+    let accepted = {
+        let own_chain = block_chain.read().unwrap();
+        let mut mempool = mempool.write().unwrap();
+        // Mark it seen only once accepted, so a rejected one can be retried later.
+        !mempool.is_seen(&tx)
+            && mempool.add(tx.clone(), own_chain.ledger())
+            && mempool.mark_seen(&tx)
+    };
+    if !accepted {
+        return Box::new(future::ok(false));
+    }
+    let relay_future = peer_apis.broadcast(handle, SyncBlockMessage::PublishTransaction(content));
+    Box::new(relay_future.map(|_| true))
+}
+
+/// Apply the reorg to own chain, and keep pending transactions valid on the new fork.
+/// Return false if the reorg can't be applied.
+pub fn reorganize(own_chain: &mut BlockChain, mempool: &RwLock<Mempool>, reorg: &Reorg) -> bool {
+    if !own_chain.apply_reorg(reorg) {
+        return false;
+    }
+    info!(
+        "reorg: roll back {} blocks and apply {} blocks after block #{}.",
+        reorg.rolled_back.len(),
+        reorg.applied.len(),
+        reorg.common_ancestor
+    );
+    let mut mempool = mempool.write().unwrap();
+    for block in reorg.rolled_back.iter() {
+        mempool.restore(block.body.transactions.clone());
+    }
+    mempool.revalidate(own_chain.ledger());
+    true
 }
 
 pub struct BlockChainSyncAPIs {
     block_chain: Arc<RwLock<BlockChain>>,
+    mempool: Arc<RwLock<Mempool>>,
     peer_apis: Arc<PeerAPIs>,
+    fork_choice: Arc<ForkChoice>,
+    orphans: Arc<RwLock<OrphanPool>>,
+    headers_sync: Arc<HeadersSync>,
+    pull_sync: PullSync,
 }
 
 impl BlockChainSyncAPIs {
-    pub fn new(block_chain: Arc<RwLock<BlockChain>>, peer_apis: Arc<PeerAPIs>) -> Self {
+    pub fn new(
+        block_chain: Arc<RwLock<BlockChain>>,
+        mempool: Arc<RwLock<Mempool>>,
+        peer_apis: Arc<PeerAPIs>,
+    ) -> Self {
+        let fork_choice = Arc::new(ForkChoice::default());
+        let headers_sync = Arc::new(HeadersSync::new(
+            block_chain.clone(),
+            mempool.clone(),
+            peer_apis.clone(),
+            fork_choice.clone(),
+        ));
+        let pull_sync = PullSync::new(block_chain.clone(), peer_apis.clone(), headers_sync.clone());
         BlockChainSyncAPIs {
             block_chain,
+            mempool,
             peer_apis,
+            fork_choice,
+            orphans: Arc::new(RwLock::new(OrphanPool::new())),
+            headers_sync,
+            pull_sync,
         }
     }
 
+    /// Spawn the background pull sync with the configured interval.
+    pub fn spawn_pull_sync(&self) {
+        let interval = NODE_CONFIG
+            .pull_sync_interval
+            .unwrap_or(DEFAULT_PULL_SYNC_INTERVAL);
+        self.pull_sync.clone().spawn(Duration::from_secs(interval));
+    }
+
     /// Response the latest block, in json format.
     /// While if there's no latest block, return NotFound as status code.
     pub fn response_latest_block(&self, state: State) -> (State, Response) {
@@ -51,85 +276,167 @@ impl BlockChainSyncAPIs {
         (state, res)
     }
 
+    /// Accept blocks published by a peer, and relay the accepted ones.
+    /// If the blocks don't connect to own chain, keep them as orphans and request missing
+    /// ancestors from the sender by headers first sync, then connect orphans to own chain.
+    /// The sender is reached at its listening port in the announcement.
+    /// A banned sender is responded Forbidden, malformed or invalid blocks penalize the sender.
     pub fn publish_block_handler(&self, mut state: State) -> Box<HandlerFuture> {
-        let cloned_chain_parse = self.block_chain.clone();
+        let sender_ip = client_addr(&state).map(|addr| addr.ip());
+        if let Some(response) = self.peer_apis.reject_banned(&state, None) {
+            return Box::new(future::ok((state, response)));
+        }
+        let cloned_chain = self.block_chain.clone();
+        let cloned_fork_choice = self.fork_choice.clone();
+        let cloned_mempool = self.mempool.clone();
+        let cloned_orphans = self.orphans.clone();
+        let cloned_peer_apis = self.peer_apis.clone();
         let parse_future = Body::take_from(&mut state).concat2().and_then(move |body| {
-            // FIXME: currently, we assumed that the body content will be in a listing style of blockchain.
-            trace!(
-                "accepting content : {} ",
-                String::from_utf8(body.to_vec()).unwrap()
-            );
-            let mut blocks: BlockChain = if let Ok(bs) = serde_json::from_slice(&body.to_vec()) {
-                bs
-            } else {
-                trace!("parse the requested block chain failed.");
-                return future::err(
-                    io::Error::new(ErrorKind::InvalidData, "parsing block data error.").into(),
-                );
+            let announcement: BlockAnnouncement = match serde_json::from_slice(&body.to_vec()) {
+                Ok(announcement) => announcement,
+                Err(_) => {
+                    trace!("parse the published blocks failed.");
+                    if let Some(ip) = sender_ip {
+                        cloned_peer_apis.penalize(&ip.to_string(), Misbehavior::MalformedMessage);
+                    }
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        "parsing block data error.",
+                    )
+                    .into());
+                }
             };
-
-            // TODO: sort the blocks by index.
-
-            let concact;
-            {
-                // TODO: remove unwrap.
-                trace!("start to parse latest block...");
-                let latest_block = blocks.get_latest().unwrap();
-                let own_chain = cloned_chain_parse.read().unwrap();
-                let own_latest_block = own_chain.get_latest().unwrap();
-                if latest_block.index < own_latest_block.index {
-                    trace!("the requested block is lower than latest block...");
-                    return future::ok(None);
+            let sender = sender_ip.and_then(|ip| peer_address(ip, announcement.port));
+            // A node of a local host is only known banned by its listening port.
+            if let Some(ref sender) = sender {
+                if cloned_peer_apis.is_banned(sender) {
+                    debug!("reject the blocks from banned peer {}.", sender);
+                    return Ok((PublishOutcome::Ignored, None, StatusCode::Forbidden));
                 }
-                concact = own_latest_block.hash == latest_block.pre_hash;
             }
-
-            if concact {
-                trace!("concatenate hash value, add to this chain ...");
-                // The received block can be concatenated after own latest block.
-                // Add it into self chain.
-                cloned_chain_parse
-                    .write()
-                    .unwrap()
-                    .add_new_block(blocks.pop_latest().unwrap());
-                // TODO: strictly consider exchange message design.
-                let self_chain = cloned_chain_parse.read().unwrap();
-                let latest_block = self_chain.get_latest().unwrap();
-                return future::ok(Some(SyncBlockMessage::PublishLatestBlock(
-                    latest_block.to_vec(),
-                )));
-            } else {
-                let new_chain;
-                {
-                    let own_chain = cloned_chain_parse.write().unwrap();
-                    new_chain = replace_to_new_chain(&own_chain, blocks);
+            let mut own_chain = cloned_chain.write().unwrap();
+            let mut orphans = cloned_orphans.write().unwrap();
+            let outcome = match accept_published_blocks(
+                &mut own_chain,
+                &cloned_mempool,
+                &cloned_fork_choice,
+                announcement.blocks.clone(),
+            ) {
+                PublishOutcome::Accepted(mut accepted) => {
+                    accepted.extend(connect_orphans(
+                        &mut own_chain,
+                        &cloned_mempool,
+                        &cloned_fork_choice,
+                        &mut orphans,
+                    ));
+                    PublishOutcome::Accepted(accepted)
                 }
-                if let Some(nc) = new_chain {
-                    let mut own_chain = cloned_chain_parse.write().unwrap();
-                    mem::replace(&mut *own_chain, nc);
-                } else {
-                    // Validation failed on replacing new chain.
-                    return future::err(
-                        io::Error::new(ErrorKind::InvalidData, "invalid chain.").into(),
-                    );
+                PublishOutcome::MissingAncestors => {
+                    for block in announcement.blocks {
+                        orphans.add(block, &own_chain);
+                    }
+                    PublishOutcome::MissingAncestors
                 }
-            }
-            future::ok(None)
+                PublishOutcome::Invalid => {
+                    if let Some(ref sender) = sender {
+                        cloned_peer_apis.penalize(sender, Misbehavior::InvalidBlocks);
+                    }
+                    PublishOutcome::Invalid
+                }
+                outcome => outcome,
+            };
+            Ok((outcome, sender, StatusCode::Ok))
         });
 
         let handle = Handle::borrow_from(&mut state).clone();
+        let cloned_chain = self.block_chain.clone();
+        let cloned_fork_choice = self.fork_choice.clone();
+        let cloned_mempool = self.mempool.clone();
+        let cloned_orphans = self.orphans.clone();
         let cloned_peer_apis = self.peer_apis.clone();
-        let notify_future = parse_future
-            .and_then(move |opt| match opt {
-                Some(msg) => {
-                    let broadcast_future = cloned_peer_apis.broadcast(handle, msg);
-                    broadcast_future
+        let cloned_headers_sync = self.headers_sync.clone();
+        let notify_future = parse_future.and_then(move |(outcome, sender, status)| {
+            let notified: BroadcastFuture = match (outcome, sender) {
+                (PublishOutcome::Accepted(blocks), _) => {
+                    publish_blocks(&cloned_peer_apis, handle, blocks)
                 }
-                None => Box::new(future::ok(None)),
-            })
-            .and_then(|_| Ok(()));
+                (PublishOutcome::MissingAncestors, Some(sender)) => {
+                    trace!("published blocks don't connect, sync from {} ...", sender);
+                    let sync_future = cloned_headers_sync.sync_from(handle.clone(), sender);
+                    Box::new(sync_future.then(move |result| {
+                        if let Err(err) = result {
+                            debug!("sync for orphans failed: {}", err);
+                        }
+                        let accepted = connect_orphans(
+                            &mut cloned_chain.write().unwrap(),
+                            &cloned_mempool,
+                            &cloned_fork_choice,
+                            &mut cloned_orphans.write().unwrap(),
+                        );
+                        // Keep spreading the blocks once connected.
+                        if accepted.is_empty() {
+                            Box::new(future::ok(None))
+                        } else {
+                            publish_blocks(&cloned_peer_apis, handle, accepted)
+                        }
+                    }))
+                }
+                _ => Box::new(future::ok(None)),
+            };
+            notified.map(move |_| status)
+        });
 
         Box::new(notify_future.then(move |result| match result {
+            Ok(status) => {
+                let res = create_response(&state, status, None);
+                Ok((state, res))
+            }
+            Err(err) => Err((state, err.into_handler_error())),
+        }))
+    }
+
+    /// Catch up with peers by headers first sync, response the height of own chain afterward.
+    pub fn sync_with_peers(&self, mut state: State) -> Box<HandlerFuture> {
+        let handle = Handle::borrow_from(&mut state).clone();
+        Box::new(
+            self.headers_sync
+                .sync(handle)
+                .then(move |result| match result {
+                    Ok(height) => {
+                        let res = create_response(
+                            &state,
+                            StatusCode::Ok,
+                            Some((serde_json::to_vec(&height).unwrap(), APPLICATION_JSON)),
+                        );
+                        Ok((state, res))
+                    }
+                    Err(err) => Err((state, err.into_handler_error())),
+                }),
+        )
+    }
+
+    /// Accept the transaction relayed by peers, and keep relaying it if it's new and valid.
+    /// Always response Ok, since a seen or invalid transaction is simply not relayed,
+    /// unless the sender host is banned, which is responded Forbidden.
+    pub fn publish_transaction_handler(&self, mut state: State) -> Box<HandlerFuture> {
+        if let Some(response) = self.peer_apis.reject_banned(&state, None) {
+            return Box::new(future::ok((state, response)));
+        }
+        let handle = Handle::borrow_from(&mut state).clone();
+        let cloned_chain = self.block_chain.clone();
+        let cloned_mempool = self.mempool.clone();
+        let cloned_peer_apis = self.peer_apis.clone();
+        let relay_future = Body::take_from(&mut state).concat2().and_then(move |body| {
+            accept_and_relay_transaction(
+                &cloned_chain,
+                &cloned_mempool,
+                &cloned_peer_apis,
+                handle,
+                body.to_vec(),
+            )
+        });
+
+        Box::new(relay_future.then(move |result| match result {
             Ok(_) => {
                 let res = create_response(&state, StatusCode::Ok, None);
                 Ok((state, res))
@@ -138,3 +445,140 @@ impl BlockChainSyncAPIs {
         }))
     }
 }
+
+#[cfg(test)]
+mod test_blockchain_sync {
+    use super::super::genesis::GenesisConfig;
+    use super::super::ledger::LedgerMode;
+    use super::*;
+    use hex;
+
+    fn mine(block_chain: &mut BlockChain, count: usize) -> Vec<Block> {
+        (0..count)
+            .map(|_| {
+                let block = block_chain.generate_next_block(Vec::new());
+                assert!(block_chain.add_new_block(block.clone()));
+                block
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_accept_published_blocks() {
+        let genesis = GenesisConfig::default();
+        let mut own_chain = BlockChain::new(&genesis, LedgerMode::Account);
+        let mut peer_chain = BlockChain::new(&genesis, LedgerMode::Account);
+        let mempool = RwLock::new(Mempool::new());
+        let fork_choice = ForkChoice::default();
+        let blocks = mine(&mut peer_chain, 3);
+
+        // Missing the first block.
+        match accept_published_blocks(&mut own_chain, &mempool, &fork_choice, blocks[1..].to_vec())
+        {
+            PublishOutcome::MissingAncestors => {}
+            outcome => panic!("unexpected outcome: {:?}", outcome),
+        }
+        match accept_published_blocks(&mut own_chain, &mempool, &fork_choice, blocks[..2].to_vec())
+        {
+            PublishOutcome::Accepted(accepted) => assert_eq!(2, accepted.len()),
+            outcome => panic!("unexpected outcome: {:?}", outcome),
+        }
+        // Known blocks are skipped.
+        match accept_published_blocks(&mut own_chain, &mempool, &fork_choice, blocks.clone()) {
+            PublishOutcome::Accepted(accepted) => assert_eq!(1, accepted.len()),
+            outcome => panic!("unexpected outcome: {:?}", outcome),
+        }
+        match accept_published_blocks(&mut own_chain, &mempool, &fork_choice, blocks.clone()) {
+            PublishOutcome::Ignored => {}
+            outcome => panic!("unexpected outcome: {:?}", outcome),
+        }
+    }
+
+    #[test]
+    fn test_reject_invalid_published_blocks() {
+        let genesis = GenesisConfig::default();
+        let mut own_chain = BlockChain::new(&genesis, LedgerMode::Account);
+        let mut peer_chain = BlockChain::new(&genesis, LedgerMode::Account);
+        let mempool = RwLock::new(Mempool::new());
+        let fork_choice = ForkChoice::default();
+        let mut blocks = mine(&mut peer_chain, 1);
+        blocks[0].header.nonce += 1;
+
+        match accept_published_blocks(&mut own_chain, &mempool, &fork_choice, blocks) {
+            PublishOutcome::Invalid => {}
+            outcome => panic!("unexpected outcome: {:?}", outcome),
+        }
+        assert_eq!(1, own_chain.len());
+    }
+
+    #[test]
+    fn test_connect_orphans() {
+        let genesis = GenesisConfig::default();
+        let mut own_chain = BlockChain::new(&genesis, LedgerMode::Account);
+        let mut peer_chain = BlockChain::new(&genesis, LedgerMode::Account);
+        let mempool = RwLock::new(Mempool::new());
+        let fork_choice = ForkChoice::default();
+        let mut orphans = OrphanPool::new();
+        let blocks = mine(&mut peer_chain, 3);
+
+        orphans.add(blocks[2].clone(), &own_chain);
+        orphans.add(blocks[1].clone(), &own_chain);
+        assert!(connect_orphans(&mut own_chain, &mempool, &fork_choice, &mut orphans).is_empty());
+
+        // The missing ancestor arrives.
+        assert!(own_chain.add_new_block(blocks[0].clone()));
+        let accepted = connect_orphans(&mut own_chain, &mempool, &fork_choice, &mut orphans);
+        assert_eq!(2, accepted.len());
+        assert_eq!(0, orphans.len());
+        assert_eq!(blocks[2].hash, own_chain.get_latest().unwrap().hash);
+    }
+
+    #[test]
+    fn test_switch_to_published_fork() {
+        let genesis = GenesisConfig::default();
+        let mut own_chain = BlockChain::new(&genesis, LedgerMode::Account);
+        let mut peer_chain = BlockChain::new(&genesis, LedgerMode::Account);
+        let mempool = RwLock::new(Mempool::new());
+        let fork_choice = ForkChoice::default();
+        mine(&mut own_chain, 1);
+        let blocks = mine(&mut peer_chain, 2);
+
+        match accept_published_blocks(&mut own_chain, &mempool, &fork_choice, blocks.clone()) {
+            PublishOutcome::Accepted(accepted) => assert_eq!(2, accepted.len()),
+            outcome => panic!("unexpected outcome: {:?}", outcome),
+        }
+        assert_eq!(blocks[1].hash, own_chain.get_latest().unwrap().hash);
+
+        let reorgs = own_chain.recent_reorgs();
+        assert_eq!(1, reorgs.len());
+        assert_eq!(0, reorgs[0].common_ancestor);
+        assert_eq!(1, reorgs[0].rolled_back.len());
+        assert_eq!(
+            vec![hex::encode(&blocks[0].hash), hex::encode(&blocks[1].hash)],
+            reorgs[0].applied
+        );
+    }
+
+    #[test]
+    fn test_reject_invalid_fork() {
+        let genesis = GenesisConfig::default();
+        let mut own_chain = BlockChain::new(&genesis, LedgerMode::Account);
+        let mut peer_chain = BlockChain::new(&genesis, LedgerMode::Account);
+        let mempool = RwLock::new(Mempool::new());
+        let fork_choice = ForkChoice::default();
+        mine(&mut own_chain, 1);
+        let mut blocks = mine(&mut peer_chain, 2);
+        blocks[1].header.nonce += 1;
+
+        match accept_published_blocks(&mut own_chain, &mempool, &fork_choice, blocks) {
+            PublishOutcome::Invalid => {}
+            outcome => panic!("unexpected outcome: {:?}", outcome),
+        }
+        // A valid fork without more work is not a fault.
+        let blocks = mine(&mut BlockChain::new(&genesis, LedgerMode::Account), 1);
+        match accept_published_blocks(&mut own_chain, &mempool, &fork_choice, blocks) {
+            PublishOutcome::Ignored => {}
+            outcome => panic!("unexpected outcome: {:?}", outcome),
+        }
+    }
+}
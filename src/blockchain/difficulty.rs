@@ -0,0 +1,120 @@
+//! The difficulty module retargets the mining difficulty by block timestamps,
+//! and measures the accumulated work of a chain.
+
+use super::blockchain::BlockHeader;
+use chrono::{DateTime, Utc};
+
+/// Expected seconds for generating a block.
+pub const BLOCK_GENERATION_INTERVAL: i64 = 10;
+
+/// Retarget the difficulty every N blocks.
+pub const DIFFICULTY_ADJUSTMENT_INTERVAL: u32 = 10;
+
+/// Acceptable drift (in seconds) of a block timestamp.
+pub const TIMESTAMP_TOLERANCE: i64 = 60;
+
+/// Parse the block time into unix timestamp in seconds.
+pub fn parse_block_time(time: &str) -> Option<i64> {
+    DateTime::parse_from_rfc3339(time)
+        .ok()
+        .map(|t| t.timestamp())
+}
+
+/// Check if the block timestamp is not too far from the previous block and the current time.
+pub fn validate_timestamp(pre_header: &BlockHeader, header: &BlockHeader) -> bool {
+    match (
+        parse_block_time(&pre_header.time),
+        parse_block_time(&header.time),
+    ) {
+        (Some(pre_time), Some(time)) => {
+            pre_time - TIMESTAMP_TOLERANCE < time
+                && time - TIMESTAMP_TOLERANCE < Utc::now().timestamp()
+        }
+        _ => false,
+    }
+}
+
+/// Calculate the difficulty of the block after the given chain.
+/// Every `DIFFICULTY_ADJUSTMENT_INTERVAL` blocks, compare the elapsed time with expected,
+/// increase the difficulty if blocks are mined too fast, decrease it if too slow.
+/// The chain could be either blocks or headers.
+pub fn next_difficulty<T: AsRef<BlockHeader>>(chain: &[T]) -> u32 {
+    let latest_block = chain.last().unwrap().as_ref();
+    let interval = DIFFICULTY_ADJUSTMENT_INTERVAL as usize;
+    if latest_block.index == 0
+        || latest_block.index % DIFFICULTY_ADJUSTMENT_INTERVAL != 0
+        || chain.len() <= interval
+    {
+        return latest_block.difficulty;
+    }
+
+    let pre_adjustment_block = chain[chain.len() - 1 - interval].as_ref();
+    let time_expected = BLOCK_GENERATION_INTERVAL * DIFFICULTY_ADJUSTMENT_INTERVAL as i64;
+    let time_taken = match (
+        parse_block_time(&pre_adjustment_block.time),
+        parse_block_time(&latest_block.time),
+    ) {
+        (Some(start), Some(end)) => end - start,
+        _ => return latest_block.difficulty,
+    };
+
+    if time_taken < time_expected / 2 {
+        latest_block.difficulty + 1
+    } else if time_taken > time_expected * 2 && latest_block.difficulty > 0 {
+        latest_block.difficulty - 1
+    } else {
+        latest_block.difficulty
+    }
+}
+
+/// The expected number of hashes to mine a block with the difficulty.
+pub fn block_work(difficulty: u32) -> u64 {
+    1u64.checked_shl(difficulty).unwrap_or(u64::max_value())
+}
+
+/// Sum up the work of all blocks in chain.
+pub fn cumulative_work<T: AsRef<BlockHeader>>(chain: &[T]) -> u64 {
+    chain.iter().fold(0, |work, b| {
+        work.saturating_add(block_work(b.as_ref().difficulty))
+    })
+}
+
+#[cfg(test)]
+mod test_difficulty {
+    use super::*;
+    use chrono::Duration;
+
+    fn blocks_with_interval(count: u32, secs: i64, difficulty: u32) -> Vec<BlockHeader> {
+        let start = Utc::now();
+        (0..count)
+            .map(|i| BlockHeader {
+                index: i,
+                time: (start + Duration::seconds(secs * i as i64)).to_rfc3339(),
+                pre_hash: Vec::new(),
+                merkle_root: Vec::new(),
+                difficulty,
+                nonce: 0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_retarget_difficulty() {
+        let count = DIFFICULTY_ADJUSTMENT_INTERVAL + 1;
+        let fast = blocks_with_interval(count, 1, 10);
+        assert_eq!(11, next_difficulty(&fast));
+        let slow = blocks_with_interval(count, BLOCK_GENERATION_INTERVAL * 3, 10);
+        assert_eq!(9, next_difficulty(&slow));
+        let steady = blocks_with_interval(count, BLOCK_GENERATION_INTERVAL, 10);
+        assert_eq!(10, next_difficulty(&steady));
+        // Not at the adjustment height.
+        let fast = blocks_with_interval(count - 1, 1, 10);
+        assert_eq!(10, next_difficulty(&fast));
+    }
+
+    #[test]
+    fn test_cumulative_work() {
+        let blocks = blocks_with_interval(3, 1, 4);
+        assert_eq!(48, cumulative_work(&blocks));
+    }
+}
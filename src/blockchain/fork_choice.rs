@@ -0,0 +1,197 @@
+//! The fork choice module decides which chain to follow when receiving a competing chain.
+
+use super::blockchain::{Block, BlockHeader};
+use super::difficulty::cumulative_work;
+use chrono::Utc;
+use hex;
+use std::panic::RefUnwindSafe;
+
+/// Maximum number of recent reorg events kept by a chain, older ones are dropped.
+pub const MAX_REORG_EVENTS: usize = 100;
+
+/// Pluggable scoring of a chain, the chain with higher score is preferred.
+/// Only headers are scored, hence a chain can be compared before its bodies are downloaded.
+/// Scores should add up over blocks, so forks are compared by their blocks after the common ancestor.
+pub trait ChainScore: Send + Sync + RefUnwindSafe {
+    fn score(&self, chain: &[BlockHeader]) -> u64;
+}
+
+/// Score a chain by the total work of its blocks.
+pub struct CumulativeWork;
+
+impl ChainScore for CumulativeWork {
+    fn score(&self, chain: &[BlockHeader]) -> u64 {
+        cumulative_work(chain)
+    }
+}
+
+/// The result of switching to another chain.
+#[derive(Debug, Serialize)]
+pub struct Reorg {
+    /// Index of the last block shared by both chains.
+    pub common_ancestor: u32,
+    /// Blocks removed from the current chain, in ascending order.
+    pub rolled_back: Vec<Block>,
+    /// Blocks appended after the common ancestor, in ascending order.
+    pub applied: Vec<Block>,
+}
+
+/// The record of an applied reorg, blocks are identified by their hex encoded hashes.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ReorgEvent {
+    /// Unix timestamp when the reorg is applied.
+    pub time: i64,
+    pub common_ancestor: u32,
+    pub rolled_back: Vec<String>,
+    pub applied: Vec<String>,
+}
+
+impl<'a> From<&'a Reorg> for ReorgEvent {
+    fn from(reorg: &'a Reorg) -> Self {
+        let hashes = |blocks: &[Block]| blocks.iter().map(|b| hex::encode(&b.hash)).collect();
+        ReorgEvent {
+            time: Utc::now().timestamp(),
+            common_ancestor: reorg.common_ancestor,
+            rolled_back: hashes(&reorg.rolled_back),
+            applied: hashes(&reorg.applied),
+        }
+    }
+}
+
+pub struct ForkChoice {
+    score: Box<ChainScore>,
+}
+
+impl ForkChoice {
+    pub fn new(score: Box<ChainScore>) -> Self {
+        ForkChoice { score }
+    }
+
+    /// Check if the candidate chain scores strictly higher than the current one.
+    pub fn prefers(&self, current: &[Block], candidate: &[Block]) -> bool {
+        self.prefers_headers(&headers_of(current), &headers_of(candidate))
+    }
+
+    /// Same as `prefers`, but only the headers of two chains are given.
+    pub fn prefers_headers(&self, current: &[BlockHeader], candidate: &[BlockHeader]) -> bool {
+        self.score.score(candidate) > self.score.score(current)
+    }
+
+    /// Choose between the current chain and the candidate one.
+    /// The candidate is adopted only if it scores strictly higher,
+    /// so ties are resolved deterministically by keeping the first seen chain.
+    /// Return the reorg to perform, or None if keeping the current chain.
+    pub fn choose(&self, current: &[Block], candidate: &[Block]) -> Option<Reorg> {
+        if !self.prefers(current, candidate) {
+            trace!("candidate chain doesn't score higher, keep the current chain.");
+            return None;
+        }
+        let ancestor = find_common_ancestor(current, candidate)?;
+        Some(Reorg {
+            common_ancestor: current[ancestor].header.index,
+            rolled_back: current[ancestor + 1..].to_vec(),
+            applied: candidate[ancestor + 1..].to_vec(),
+        })
+    }
+
+    /// Same as `choose`, but the candidate is the fork following the block at ancestor position,
+    /// so only blocks after the common ancestor are compared.
+    pub fn choose_fork(
+        &self,
+        current: &[Block],
+        ancestor: usize,
+        fork: Vec<Block>,
+    ) -> Option<Reorg> {
+        let rolled_back = &current[ancestor + 1..];
+        if !self.prefers(rolled_back, &fork) {
+            trace!("fork doesn't score higher, keep the current chain.");
+            return None;
+        }
+        Some(Reorg {
+            common_ancestor: current[ancestor].header.index,
+            rolled_back: rolled_back.to_vec(),
+            applied: fork,
+        })
+    }
+}
+
+impl Default for ForkChoice {
+    fn default() -> Self {
+        ForkChoice::new(Box::new(CumulativeWork))
+    }
+}
+
+fn headers_of(chain: &[Block]) -> Vec<BlockHeader> {
+    chain.iter().map(|block| block.header.clone()).collect()
+}
+
+/// Find the position of the last block shared by both chains.
+/// Return None if two chains don't even share the genesis block.
+pub fn find_common_ancestor(chain: &[Block], other: &[Block]) -> Option<usize> {
+    chain
+        .iter()
+        .zip(other.iter())
+        .take_while(|&(a, b)| a.hash == b.hash)
+        .count()
+        .checked_sub(1)
+}
+
+#[cfg(test)]
+mod test_fork_choice {
+    use super::super::blockchain::{BlockBody, BlockHeader};
+    use super::*;
+
+    /// Score a chain by its length, i.e. the naive longest chain rule.
+    struct LongestChain;
+
+    impl ChainScore for LongestChain {
+        fn score(&self, chain: &[BlockHeader]) -> u64 {
+            chain.len() as u64
+        }
+    }
+
+    fn block(index: u32, hash: u8, difficulty: u32) -> Block {
+        Block {
+            hash: vec![hash],
+            header: BlockHeader {
+                index,
+                time: String::new(),
+                pre_hash: Vec::new(),
+                merkle_root: Vec::new(),
+                difficulty,
+                nonce: 0,
+            },
+            body: BlockBody::default(),
+        }
+    }
+
+    #[test]
+    fn test_choose_heavier_chain() {
+        let current = vec![block(0, 0, 1), block(1, 1, 1), block(2, 2, 1)];
+        let candidate = vec![block(0, 0, 1), block(1, 3, 4)];
+        let reorg = ForkChoice::default().choose(&current, &candidate).unwrap();
+        assert_eq!(0, reorg.common_ancestor);
+        assert_eq!(2, reorg.rolled_back.len());
+        assert_eq!(vec![3], reorg.applied[0].hash);
+
+        // The longer but lighter chain loses with cumulative work.
+        assert!(ForkChoice::default().choose(&candidate, &current).is_none());
+        let longest = ForkChoice::new(Box::new(LongestChain));
+        assert!(longest.choose(&candidate, &current).is_some());
+    }
+
+    #[test]
+    fn test_keep_first_seen_on_tie() {
+        let current = vec![block(0, 0, 1), block(1, 1, 1)];
+        let candidate = vec![block(0, 0, 1), block(1, 2, 1)];
+        assert!(ForkChoice::default().choose(&current, &candidate).is_none());
+    }
+
+    #[test]
+    fn test_no_common_ancestor() {
+        let current = vec![block(0, 0, 1)];
+        let candidate = vec![block(0, 1, 1), block(1, 2, 1)];
+        assert!(find_common_ancestor(&current, &candidate).is_none());
+        assert!(ForkChoice::default().choose(&current, &candidate).is_none());
+    }
+}
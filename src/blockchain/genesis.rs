@@ -0,0 +1,46 @@
+//! The genesis module defines the configurable, deterministic genesis block.
+
+use super::blockchain::INITIAL_DIFFICULTY;
+use serde_json;
+use std::fs::File;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+
+/// Initial balance assigned to an address in the genesis block.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Allocation {
+    pub address: String,
+    pub amount: u64,
+}
+
+/// The contents of genesis block, nodes sharing the same config share the same genesis hash.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GenesisConfig {
+    pub network_id: String,
+    /// RFC 3339 formatted time of genesis block.
+    pub timestamp: String,
+    pub data: String,
+    pub initial_difficulty: u32,
+    #[serde(default)]
+    pub premine: Vec<Allocation>,
+}
+
+impl Default for GenesisConfig {
+    fn default() -> Self {
+        GenesisConfig {
+            network_id: "bam-chain".to_string(),
+            timestamp: "2018-05-01T00:00:00+00:00".to_string(),
+            data: "Genesis block.".to_string(),
+            initial_difficulty: INITIAL_DIFFICULTY,
+            premine: Vec::new(),
+        }
+    }
+}
+
+impl GenesisConfig {
+    /// Load the genesis config from a json file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        serde_json::from_reader(file).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+    }
+}
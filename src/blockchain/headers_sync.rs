@@ -0,0 +1,512 @@
+//! The headers sync module catches up with peers by downloading headers first.
+//! The header chain and its work are validated before any block body is downloaded,
+//! then bodies are fetched in parallel from all peers.
+
+use super::blockchain::{validate_header, Block, BlockBody, BlockChain, BlockHeader};
+use super::blockchain_sync::reorganize;
+use super::difficulty::next_difficulty;
+use super::fork_choice::ForkChoice;
+use super::mempool::Mempool;
+use super::transaction::transactions_root;
+use futures::future::{self, Loop};
+use futures::{stream, Future, Stream};
+use hyper;
+use peers::ban::Misbehavior;
+use peers::{http_get, is_timeout, with_timeout, PeerAPIs};
+use serde_json;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, ErrorKind};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use tokio_core::reactor::Handle;
+
+/// Maximum number of headers responsed by a single request.
+pub const MAX_HEADERS_PER_REQUEST: u32 = 500;
+
+/// Headers are requested from this number of blocks below own tip, for locating forks.
+pub const FORK_LOOKBACK: u32 = 100;
+
+/// Maximum number of bodies downloaded in parallel.
+pub const MAX_PARALLEL_BODY_DOWNLOADS: usize = 16;
+
+/// Seconds waiting for a peer to respond a headers page or a body.
+const REQUEST_TIMEOUT: u64 = 10;
+
+/// Check if the first header links to a block of own chain.
+fn links_to_own_chain(own_chain: &BlockChain, first: Option<&BlockHeader>) -> bool {
+    match first {
+        Some(first) if first.index > 0 => own_chain
+            .get_block(first.index - 1)
+            .map_or(false, |block| block.hash == first.pre_hash),
+        _ => false,
+    }
+}
+
+/// Progress of an ongoing sync, kept between attempts so that an interrupted sync resumes.
+#[derive(Debug, Default)]
+pub struct SyncProgress {
+    /// The last block shared with own chain.
+    ancestor: u32,
+    ancestor_hash: Vec<u8>,
+    /// Validated headers after the ancestor, not applied to own chain yet.
+    headers: Vec<BlockHeader>,
+    /// Downloaded bodies of the headers, by block index.
+    bodies: HashMap<u32, BlockBody>,
+}
+
+impl SyncProgress {
+    /// Check if own chain has moved away from the ancestor since the sync started.
+    fn is_stale(&self, own_chain: &BlockChain) -> bool {
+        !self.headers.is_empty()
+            && own_chain
+                .get_block(self.ancestor)
+                .map_or(true, |block| block.hash != self.ancestor_hash)
+    }
+
+    /// The index to request headers from.
+    pub fn next_request(&self, own_chain: &BlockChain) -> u32 {
+        match self.headers.last() {
+            Some(header) => header.index + 1,
+            None => {
+                let tip = own_chain.get_latest().unwrap().header.index;
+                tip.saturating_sub(FORK_LOOKBACK).max(1)
+            }
+        }
+    }
+
+    /// The ancestor headers of own chain followed by downloaded headers.
+    fn candidate_headers(&self, own_chain: &BlockChain) -> Vec<BlockHeader> {
+        let mut candidate: Vec<BlockHeader> = own_chain
+            .headers(0, self.ancestor + 1)
+            .into_iter()
+            .cloned()
+            .collect();
+        candidate.extend(self.headers.iter().cloned());
+        candidate
+    }
+
+    /// Validate and append the headers responsed by a peer.
+    /// At the beginning of a sync, headers already in own chain are skipped,
+    /// and the last of them becomes the common ancestor.
+    /// Return false if headers don't link to own chain or downloaded ones, or are invalid.
+    /// An empty page means the peer has nothing more, which is not a failure.
+    pub fn extend_headers(&mut self, own_chain: &BlockChain, headers: Vec<BlockHeader>) -> bool {
+        if headers.is_empty() {
+            return true;
+        }
+        let mut headers = headers.into_iter().peekable();
+        if self.headers.is_empty() {
+            if !links_to_own_chain(own_chain, headers.peek()) {
+                debug!("sync failed: headers don't link to own chain.");
+                return false;
+            }
+            self.ancestor = headers.peek().unwrap().index - 1;
+            while let Some(index) = headers.peek().and_then(|header| {
+                own_chain
+                    .get_block(header.index)
+                    .filter(|block| block.hash == header.hash())
+                    .map(|_| header.index)
+            }) {
+                self.ancestor = index;
+                headers.next();
+            }
+            self.ancestor_hash = own_chain.get_block(self.ancestor).unwrap().hash.clone();
+        }
+
+        let mut context = self.candidate_headers(own_chain);
+        for header in headers {
+            let difficulty = next_difficulty(&context);
+            if !validate_header(context.last().unwrap(), &header, &header.hash(), difficulty) {
+                return false;
+            }
+            context.push(header.clone());
+            self.headers.push(header);
+        }
+        true
+    }
+
+    /// Check if the downloaded header chain has more work than own chain.
+    pub fn is_preferred(&self, own_chain: &BlockChain, fork_choice: &ForkChoice) -> bool {
+        let own_headers: Vec<BlockHeader> = own_chain
+            .headers(0, u32::max_value())
+            .into_iter()
+            .cloned()
+            .collect();
+        fork_choice.prefers_headers(&own_headers, &self.candidate_headers(own_chain))
+    }
+
+    /// Indexes of blocks whose bodies are not downloaded yet.
+    pub fn missing_bodies(&self) -> Vec<u32> {
+        self.headers
+            .iter()
+            .map(|header| header.index)
+            .filter(|index| !self.bodies.contains_key(index))
+            .collect()
+    }
+
+    /// Accept the body if it matches the merkle root of downloaded header.
+    pub fn add_body(&mut self, index: u32, body: BlockBody) -> bool {
+        let matched = self
+            .headers
+            .iter()
+            .find(|header| header.index == index)
+            .map_or(false, |header| {
+                transactions_root(&body.transactions) == header.merkle_root
+            });
+        if matched {
+            self.bodies.insert(index, body);
+        } else {
+            debug!(
+                "sync failed: body of block #{} doesn't match the header.",
+                index
+            );
+        }
+        matched
+    }
+
+    /// Assemble blocks once all bodies are downloaded, and reset the progress.
+    /// Return the common ancestor and blocks after it.
+    pub fn take_blocks(&mut self) -> Option<(u32, Vec<Block>)> {
+        if self.headers.is_empty() || !self.missing_bodies().is_empty() {
+            return None;
+        }
+        let progress = ::std::mem::replace(self, SyncProgress::default());
+        let mut bodies = progress.bodies;
+        let blocks = progress
+            .headers
+            .into_iter()
+            .map(|header| {
+                let body = bodies.remove(&header.index).unwrap();
+                Block::new(header, body)
+            })
+            .collect();
+        Some((progress.ancestor, blocks))
+    }
+}
+
+pub struct HeadersSync {
+    block_chain: Arc<RwLock<BlockChain>>,
+    mempool: Arc<RwLock<Mempool>>,
+    peer_apis: Arc<PeerAPIs>,
+    fork_choice: Arc<ForkChoice>,
+    /// Interrupted syncs by the peer which headers are downloaded from,
+    /// so that peers on different forks don't mix up their headers.
+    progress: Arc<Mutex<HashMap<String, Arc<RwLock<SyncProgress>>>>>,
+    /// Peers being synced from, at most one sync runs against a peer at a time.
+    syncing: Arc<Mutex<HashSet<String>>>,
+    /// Rotate the peer which headers are downloaded from, so a faulty peer can't stall the sync.
+    rounds: AtomicUsize,
+}
+
+impl HeadersSync {
+    pub fn new(
+        block_chain: Arc<RwLock<BlockChain>>,
+        mempool: Arc<RwLock<Mempool>>,
+        peer_apis: Arc<PeerAPIs>,
+        fork_choice: Arc<ForkChoice>,
+    ) -> Self {
+        HeadersSync {
+            block_chain,
+            mempool,
+            peer_apis,
+            fork_choice,
+            progress: Arc::new(Mutex::new(HashMap::new())),
+            syncing: Arc::new(Mutex::new(HashSet::new())),
+            rounds: AtomicUsize::new(0),
+        }
+    }
+
+    /// Sync with peers, resolve to the height of own chain afterward.
+    /// The workflow will be:
+    /// 1. Download headers from a peer, page by page, validating each page.
+    /// 2. Stop if the header chain doesn't have more work than own chain.
+    /// 3. Download missing bodies from all peers in parallel.
+    /// 4. Switch to the new chain once all bodies are downloaded,
+    ///    otherwise the downloaded part is kept for the next attempt.
+    pub fn sync(&self, handle: Handle) -> Box<Future<Item = u32, Error = hyper::Error>> {
+        let peers = self.peer_apis.peer_addresses();
+        if peers.is_empty() {
+            return Box::new(future::ok(self.height()));
+        }
+        let round = self.rounds.fetch_add(1, Ordering::SeqCst);
+        let headers_peer = peers[round % peers.len()].clone();
+        self.sync_from(handle, headers_peer)
+    }
+
+    /// Same as `sync`, but download headers from the given peer,
+    /// which also serves bodies even if it's not a known peer.
+    /// Skipped if a sync from the peer is already running.
+    pub fn sync_from(
+        &self,
+        handle: Handle,
+        headers_peer: String,
+    ) -> Box<Future<Item = u32, Error = hyper::Error>> {
+        if !self.syncing.lock().unwrap().insert(headers_peer.clone()) {
+            trace!("already syncing from {}.", headers_peer);
+            return Box::new(future::ok(self.height()));
+        }
+        let mut peers = self.peer_apis.peer_addresses();
+        if !peers.contains(&headers_peer) {
+            peers.push(headers_peer.clone());
+        }
+
+        let progress = self
+            .progress
+            .lock()
+            .unwrap()
+            .entry(headers_peer.clone())
+            .or_insert_with(|| Arc::new(RwLock::new(SyncProgress::default())))
+            .clone();
+        {
+            let own_chain = self.block_chain.read().unwrap();
+            let mut progress = progress.write().unwrap();
+            if progress.is_stale(&own_chain) {
+                trace!("own chain moved since last sync, restart sync.");
+                *progress = SyncProgress::default();
+            }
+        }
+
+        let headers_future =
+            self.download_headers(handle.clone(), headers_peer.clone(), progress.clone());
+
+        let cloned_chain = self.block_chain.clone();
+        let cloned_progress = progress.clone();
+        let cloned_fork_choice = self.fork_choice.clone();
+        let cloned_peer_apis = self.peer_apis.clone();
+        let bodies_future = headers_future.and_then(move |_| {
+            let missing = {
+                let own_chain = cloned_chain.read().unwrap();
+                let mut progress = cloned_progress.write().unwrap();
+                if progress.headers.is_empty() {
+                    trace!("peer is not ahead, nothing to sync.");
+                    return future::Either::A(future::ok(()));
+                }
+                if !progress.is_preferred(&own_chain, &cloned_fork_choice) {
+                    trace!("headers of peer don't have more work, stop sync.");
+                    *progress = SyncProgress::default();
+                    return future::Either::A(future::ok(()));
+                }
+                progress.missing_bodies()
+            };
+            let body_futures =
+                stream::iter_ok(missing.into_iter().enumerate()).map(move |(i, index)| {
+                    let peer = &peers[i % peers.len()];
+                    download_body(
+                        &handle,
+                        peer,
+                        index,
+                        cloned_progress.clone(),
+                        cloned_peer_apis.clone(),
+                    )
+                });
+            future::Either::B(
+                body_futures
+                    .buffer_unordered(MAX_PARALLEL_BODY_DOWNLOADS)
+                    .for_each(|_| Ok(())),
+            )
+        });
+
+        let cloned_chain = self.block_chain.clone();
+        let cloned_mempool = self.mempool.clone();
+        let cloned_progress = progress.clone();
+        let cloned_fork_choice = self.fork_choice.clone();
+        let sync_future = bodies_future.map(move |_| {
+            let mut own_chain = cloned_chain.write().unwrap();
+            let blocks = cloned_progress.write().unwrap().take_blocks();
+            if let Some((ancestor, blocks)) = blocks {
+                match own_chain.reorg_to(ancestor, blocks, &cloned_fork_choice) {
+                    Some(ref reorg) if reorganize(&mut own_chain, &cloned_mempool, reorg) => {}
+                    _ => debug!("sync failed: downloaded blocks are invalid."),
+                }
+            }
+            own_chain.get_latest().unwrap().header.index
+        });
+
+        // Only interrupted progress is kept for the next attempt.
+        let cloned_progress_map = self.progress.clone();
+        let cloned_syncing = self.syncing.clone();
+        Box::new(sync_future.then(move |result| {
+            if progress.read().unwrap().headers.is_empty() {
+                cloned_progress_map.lock().unwrap().remove(&headers_peer);
+            }
+            cloned_syncing.lock().unwrap().remove(&headers_peer);
+            result
+        }))
+    }
+
+    fn height(&self) -> u32 {
+        self.block_chain
+            .read()
+            .unwrap()
+            .get_latest()
+            .unwrap()
+            .header
+            .index
+    }
+
+    /// Download headers page by page until the peer has no more.
+    /// Invalid headers discard the progress and penalize the peer,
+    /// and downloaded pages are kept on network errors. A page timing out penalizes the peer.
+    fn download_headers(
+        &self,
+        handle: Handle,
+        peer: String,
+        progress: Arc<RwLock<SyncProgress>>,
+    ) -> Box<Future<Item = (), Error = hyper::Error>> {
+        let cloned_chain = self.block_chain.clone();
+        let cloned_progress = progress;
+        let cloned_peer_apis = self.peer_apis.clone();
+        Box::new(future::loop_fn((), move |_| {
+            let from = {
+                let own_chain = cloned_chain.read().unwrap();
+                cloned_progress.read().unwrap().next_request(&own_chain)
+            };
+            let url = format!(
+                "http://{}/headers?from={}&count={}",
+                peer, from, MAX_HEADERS_PER_REQUEST
+            );
+            trace!("download headers from : {} ...", url);
+            let cloned_chain = cloned_chain.clone();
+            let cloned_progress = cloned_progress.clone();
+            let cloned_peer_apis = cloned_peer_apis.clone();
+            let peer = peer.clone();
+            let timeout_peer_apis = cloned_peer_apis.clone();
+            let timeout_peer = peer.clone();
+            let page_future = with_timeout(&handle, http_get(&handle, &url), REQUEST_TIMEOUT)
+                .map_err(move |err| {
+                    if is_timeout(&err) {
+                        timeout_peer_apis.penalize(&timeout_peer, Misbehavior::Timeout);
+                    }
+                    err
+                });
+            page_future.and_then(move |content| {
+                let headers: Vec<BlockHeader> = serde_json::from_slice(&content).map_err(|_| {
+                    cloned_peer_apis.penalize(&peer, Misbehavior::MalformedMessage);
+                    io::Error::new(ErrorKind::InvalidData, "parsing headers error.")
+                })?;
+                let page_len = headers.len() as u32;
+                let own_chain = cloned_chain.read().unwrap();
+                let mut progress = cloned_progress.write().unwrap();
+                // Headers not linking to own chain may come from a deep fork, which is not a fault,
+                // while downloaded headers are only responsed by this peer, which it must follow.
+                let linked =
+                    !progress.headers.is_empty() || links_to_own_chain(&own_chain, headers.first());
+                if !progress.extend_headers(&own_chain, headers) {
+                    *progress = SyncProgress::default();
+                    if linked {
+                        cloned_peer_apis.penalize(&peer, Misbehavior::InvalidBlocks);
+                    }
+                    return Err(io::Error::new(ErrorKind::InvalidData, "invalid headers.").into());
+                }
+                if page_len < MAX_HEADERS_PER_REQUEST {
+                    Ok(Loop::Break(()))
+                } else {
+                    Ok(Loop::Continue(()))
+                }
+            })
+        }))
+    }
+}
+
+/// Download the body of the block from the peer into progress.
+/// Failures and timeouts are ignored, the body will be requested again in the next sync.
+/// The peer is penalized if the body is malformed or doesn't match the header.
+fn download_body(
+    handle: &Handle,
+    peer: &str,
+    index: u32,
+    progress: Arc<RwLock<SyncProgress>>,
+    peer_apis: Arc<PeerAPIs>,
+) -> Box<Future<Item = (), Error = hyper::Error>> {
+    let url = format!("http://{}/body/{}", peer, index);
+    trace!("download body from : {} ...", url);
+    let peer = peer.to_string();
+    let body_future = with_timeout(handle, http_get(handle, &url), REQUEST_TIMEOUT);
+    Box::new(body_future.then(move |result| {
+        let content = match result {
+            Ok(content) => content,
+            Err(err) => {
+                debug!("download body of block #{} failed: {}", index, err);
+                return Ok(());
+            }
+        };
+        match serde_json::from_slice(&content) {
+            Ok(body) => {
+                if !progress.write().unwrap().add_body(index, body) {
+                    peer_apis.penalize(&peer, Misbehavior::InvalidBlocks);
+                }
+            }
+            Err(_) => {
+                debug!("parse body of block #{} failed.", index);
+                peer_apis.penalize(&peer, Misbehavior::MalformedMessage);
+            }
+        }
+        Ok(())
+    }))
+}
+
+#[cfg(test)]
+mod test_headers_sync {
+    use super::super::genesis::GenesisConfig;
+    use super::super::ledger::LedgerMode;
+    use super::*;
+
+    /// Own chain with one block, and a peer chain with three blocks on another fork.
+    fn forked_chains() -> (BlockChain, BlockChain) {
+        let genesis = GenesisConfig::default();
+        let mut own_chain = BlockChain::new(&genesis, LedgerMode::Account);
+        let block = own_chain.generate_next_block(Vec::new());
+        assert!(own_chain.add_new_block(block));
+        let mut peer_chain = BlockChain::new(&genesis, LedgerMode::Account);
+        for _ in 0..3 {
+            let block = peer_chain.generate_next_block(Vec::new());
+            assert!(peer_chain.add_new_block(block));
+        }
+        (own_chain, peer_chain)
+    }
+
+    #[test]
+    fn test_sync_to_heavier_fork() {
+        let (mut own_chain, peer_chain) = forked_chains();
+        let mut progress = SyncProgress::default();
+        let headers = peer_chain.headers(1, MAX_HEADERS_PER_REQUEST);
+        assert!(progress.extend_headers(&own_chain, headers.into_iter().cloned().collect()));
+        assert!(progress.is_preferred(&own_chain, &ForkChoice::default()));
+        assert_eq!(vec![1, 2, 3], progress.missing_bodies());
+
+        // Resume with the remaining bodies.
+        for index in 1..4 {
+            assert!(progress.take_blocks().is_none());
+            let body = peer_chain.get_block(index).unwrap().body.clone();
+            assert!(progress.add_body(index, body));
+        }
+        let (ancestor, blocks) = progress.take_blocks().unwrap();
+        assert_eq!(0, ancestor);
+        let reorg = own_chain
+            .reorg_to(ancestor, blocks, &ForkChoice::default())
+            .unwrap();
+        assert!(own_chain.apply_reorg(&reorg));
+        assert_eq!(
+            peer_chain.get_latest().unwrap().hash,
+            own_chain.get_latest().unwrap().hash
+        );
+    }
+
+    #[test]
+    fn test_reject_invalid_headers() {
+        let (own_chain, peer_chain) = forked_chains();
+        let mut headers: Vec<BlockHeader> = peer_chain
+            .headers(1, MAX_HEADERS_PER_REQUEST)
+            .into_iter()
+            .cloned()
+            .collect();
+        headers[1].nonce += 1;
+        assert!(!SyncProgress::default().extend_headers(&own_chain, headers.clone()));
+        // Headers which don't link to own chain.
+        assert!(!SyncProgress::default().extend_headers(&own_chain, headers.split_off(2)));
+        // The peer is not ahead.
+        let mut progress = SyncProgress::default();
+        assert!(progress.extend_headers(&own_chain, Vec::new()));
+        assert!(progress.missing_bodies().is_empty());
+    }
+}
@@ -0,0 +1,180 @@
+//! The account module derives account balances and nonces by replaying the chain.
+
+use super::Ledger;
+use blockchain::genesis::Allocation;
+use blockchain::transaction::Transaction;
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Account {
+    pub balance: u64,
+    /// The nonce expected by the next transaction sent from this account.
+    pub nonce: u64,
+}
+
+/// The world state of all accounts.
+#[derive(Clone, Debug, Default)]
+pub struct AccountLedger {
+    accounts: HashMap<String, Account>,
+}
+
+impl AccountLedger {
+    /// Create a ledger holding the genesis allocations.
+    pub fn new(premine: &[Allocation]) -> Self {
+        let mut ledger = AccountLedger::default();
+        for allocation in premine {
+            ledger
+                .accounts
+                .entry(allocation.address.clone())
+                .or_insert_with(Account::default)
+                .balance += allocation.amount;
+        }
+        ledger
+    }
+}
+
+impl Ledger for AccountLedger {
+    fn account(&self, address: &str) -> Option<Account> {
+        Some(self.accounts.get(address).cloned().unwrap_or_default())
+    }
+
+    fn balance(&self, address: &str) -> u64 {
+        self.accounts.get(address).map_or(0, |a| a.balance)
+    }
+
+    /// Check if the transaction can be applied to current state,
+    /// i.e. the nonce is the expected one and the sender can afford it.
+    fn validate_transaction(&self, tx: &Transaction) -> bool {
+        if tx.is_coinbase() {
+            return true;
+        }
+        let sender = self.accounts.get(&tx.sender).cloned().unwrap_or_default();
+        if tx.nonce != sender.nonce {
+            debug!("validation failed: invalid transaction nonce, maybe a replay.");
+            return false;
+        }
+        match tx.amount.checked_add(tx.fee) {
+            Some(cost) if cost <= sender.balance => true,
+            _ => {
+                debug!("validation failed: transaction overspends sender balance.");
+                false
+            }
+        }
+    }
+
+    fn apply_transaction(&mut self, tx: &Transaction) {
+        if !tx.is_coinbase() {
+            // A zero cost transaction is valid even from a sender never seen.
+            let sender = self
+                .accounts
+                .entry(tx.sender.clone())
+                .or_insert_with(Account::default);
+            sender.balance -= tx.amount + tx.fee;
+            sender.nonce += 1;
+        }
+        self.accounts
+            .entry(tx.recipient.clone())
+            .or_insert_with(Account::default)
+            .balance += tx.amount;
+    }
+
+    fn rollback_transaction(&mut self, tx: &Transaction) {
+        self.accounts.get_mut(&tx.recipient).unwrap().balance -= tx.amount;
+        if !tx.is_coinbase() {
+            let sender = self.accounts.get_mut(&tx.sender).unwrap();
+            sender.balance += tx.amount + tx.fee;
+            sender.nonce -= 1;
+        }
+    }
+
+    fn clone_box(&self) -> Box<Ledger> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod test_account {
+    use super::*;
+    use blockchain::blockchain::{Block, BlockBody, BlockHeader};
+    use ed25519_dalek::Keypair;
+    use hex;
+    use rand::rngs::OsRng;
+
+    fn block_with(transactions: Vec<Transaction>) -> Block {
+        Block {
+            hash: Vec::new(),
+            header: BlockHeader {
+                index: 1,
+                time: String::new(),
+                pre_hash: Vec::new(),
+                merkle_root: Vec::new(),
+                difficulty: 0,
+                nonce: 0,
+            },
+            body: BlockBody { transactions },
+        }
+    }
+
+    fn funded_ledger(keypair: &Keypair, amount: u64) -> AccountLedger {
+        AccountLedger::new(&[Allocation {
+            address: hex::encode(keypair.public.as_bytes()),
+            amount,
+        }])
+    }
+
+    #[test]
+    fn test_apply_and_rollback() {
+        let keypair = Keypair::generate(&mut OsRng);
+        let sender = hex::encode(keypair.public.as_bytes());
+        let mut ledger = funded_ledger(&keypair, 100);
+        let block = block_with(vec![
+            Transaction::coinbase("miner", 52, 1),
+            Transaction::new_signed(&keypair, "bob", 30, 1, 0),
+            Transaction::new_signed(&keypair, "bob", 20, 1, 1),
+        ]);
+        assert!(ledger.apply_block(&block));
+        assert_eq!(48, ledger.balance(&sender));
+        assert_eq!(2, ledger.account(&sender).unwrap().nonce);
+        assert_eq!(50, ledger.balance("bob"));
+        assert_eq!(52, ledger.balance("miner"));
+
+        ledger.rollback_block(&block);
+        assert_eq!(100, ledger.balance(&sender));
+        assert_eq!(0, ledger.account(&sender).unwrap().nonce);
+        assert_eq!(0, ledger.balance("bob"));
+        assert_eq!(0, ledger.balance("miner"));
+    }
+
+    #[test]
+    fn test_reject_overspend_and_replay() {
+        let keypair = Keypair::generate(&mut OsRng);
+        let sender = hex::encode(keypair.public.as_bytes());
+        let mut ledger = funded_ledger(&keypair, 100);
+
+        let tx = Transaction::new_signed(&keypair, "bob", 60, 1, 0);
+        let overspend = block_with(vec![
+            tx.clone(),
+            Transaction::new_signed(&keypair, "bob", 60, 1, 1),
+        ]);
+        assert!(!ledger.apply_block(&overspend));
+        // The partially applied block is reverted.
+        assert_eq!(100, ledger.balance(&sender));
+
+        assert!(ledger.apply_block(&block_with(vec![tx.clone()])));
+        assert!(!ledger.apply_block(&block_with(vec![tx])));
+        assert_eq!(39, ledger.balance(&sender));
+    }
+
+    #[test]
+    fn test_zero_cost_from_unfunded_sender() {
+        let keypair = Keypair::generate(&mut OsRng);
+        let sender = hex::encode(keypair.public.as_bytes());
+        let mut ledger = AccountLedger::default();
+        let block = block_with(vec![Transaction::new_signed(&keypair, "bob", 0, 0, 0)]);
+        assert!(ledger.apply_block(&block));
+        assert_eq!(1, ledger.account(&sender).unwrap().nonce);
+
+        ledger.rollback_block(&block);
+        assert_eq!(0, ledger.account(&sender).unwrap().nonce);
+    }
+}
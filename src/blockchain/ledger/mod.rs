@@ -0,0 +1,82 @@
+//! The ledger module derives the state of value from blocks.
+//! Two models are supported, selected by the node config:
+//! the account model tracking balances and nonces, and the bitcoin-like utxo model.
+
+pub mod account;
+pub mod utxo;
+
+use self::account::{Account, AccountLedger};
+use self::utxo::{Utxo, UtxoSet};
+use super::blockchain::Block;
+use super::genesis::Allocation;
+use super::transaction::Transaction;
+use std::fmt::Debug;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LedgerMode {
+    Account,
+    Utxo,
+}
+
+impl Default for LedgerMode {
+    fn default() -> Self {
+        LedgerMode::Account
+    }
+}
+
+/// The state replayed from blocks, which validates transactions of incoming blocks.
+pub trait Ledger: Debug + Send + Sync {
+    /// The account of address, None if the ledger doesn't use the account model.
+    fn account(&self, _address: &str) -> Option<Account> {
+        None
+    }
+
+    /// The unspent outputs of address, None if the ledger doesn't use the utxo model.
+    fn unspent_outputs(&self, _address: &str) -> Option<Vec<Utxo>> {
+        None
+    }
+
+    fn balance(&self, address: &str) -> u64;
+
+    /// Check if the transaction can be applied to current state.
+    fn validate_transaction(&self, tx: &Transaction) -> bool;
+
+    /// Apply a validated transaction.
+    fn apply_transaction(&mut self, tx: &Transaction);
+
+    /// Revert a transaction previously applied by `apply_transaction`.
+    fn rollback_transaction(&mut self, tx: &Transaction);
+
+    fn clone_box(&self) -> Box<Ledger>;
+
+    /// Apply all transactions of the block in order.
+    /// If any of them is invalid, the state is left untouched and return false.
+    fn apply_block(&mut self, block: &Block) -> bool {
+        for (i, tx) in block.body.transactions.iter().enumerate() {
+            if !self.validate_transaction(tx) {
+                for applied in block.body.transactions[..i].iter().rev() {
+                    self.rollback_transaction(applied);
+                }
+                return false;
+            }
+            self.apply_transaction(tx);
+        }
+        true
+    }
+
+    /// Revert a block previously applied by `apply_block`.
+    fn rollback_block(&mut self, block: &Block) {
+        for tx in block.body.transactions.iter().rev() {
+            self.rollback_transaction(tx);
+        }
+    }
+}
+
+/// Create a ledger of the mode, holding the genesis allocations.
+pub fn new_ledger(mode: LedgerMode, genesis: &Block, premine: &[Allocation]) -> Box<Ledger> {
+    match mode {
+        LedgerMode::Account => Box::new(AccountLedger::new(premine)),
+        LedgerMode::Utxo => Box::new(UtxoSet::new(genesis, premine)),
+    }
+}
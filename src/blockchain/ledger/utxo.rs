@@ -0,0 +1,221 @@
+//! The utxo module tracks unspent transaction outputs, i.e. the bitcoin-like ledger model.
+//!
+//! Outputs of a transaction are implied by its fields, the first output pays the amount to
+//! recipient, and the second one returns the change, i.e. inputs minus amount and fee, to sender.
+
+use super::Ledger;
+use blockchain::blockchain::Block;
+use blockchain::genesis::Allocation;
+use blockchain::transaction::{OutPoint, Transaction};
+use hex;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TxOutput {
+    pub address: String,
+    pub amount: u64,
+}
+
+/// An unspent output along with its reference.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Utxo {
+    pub out_point: OutPoint,
+    pub amount: u64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct UtxoSet {
+    unspent: HashMap<OutPoint, TxOutput>,
+    /// Outputs spent by each applied transaction, keyed by transaction hash, for rolling back.
+    spent: HashMap<String, Vec<(OutPoint, TxOutput)>>,
+}
+
+impl UtxoSet {
+    /// Create a utxo set holding the genesis allocations,
+    /// which are referred as outputs of the genesis block hash.
+    pub fn new(genesis: &Block, premine: &[Allocation]) -> Self {
+        let mut utxo_set = UtxoSet::default();
+        let tx_hash = hex::encode(&genesis.hash);
+        for (i, allocation) in premine.iter().enumerate() {
+            utxo_set.unspent.insert(
+                OutPoint {
+                    tx_hash: tx_hash.clone(),
+                    index: i as u32,
+                },
+                TxOutput {
+                    address: allocation.address.clone(),
+                    amount: allocation.amount,
+                },
+            );
+        }
+        utxo_set
+    }
+
+    /// The outputs created by the transaction, paid to recipient and the change to sender.
+    fn outputs(&self, tx: &Transaction) -> Vec<TxOutput> {
+        let mut outputs = vec![TxOutput {
+            address: tx.recipient.clone(),
+            amount: tx.amount,
+        }];
+        if !tx.is_coinbase() {
+            let change = self.input_amount(tx).unwrap() - tx.amount - tx.fee;
+            if change > 0 {
+                outputs.push(TxOutput {
+                    address: tx.sender.clone(),
+                    amount: change,
+                });
+            }
+        }
+        outputs
+    }
+
+    /// Sum up the inputs, None if any of them is not spendable by the sender.
+    fn input_amount(&self, tx: &Transaction) -> Option<u64> {
+        tx.inputs.iter().fold(Some(0u64), |sum, input| {
+            match (sum, self.unspent.get(input)) {
+                (Some(s), Some(output)) if output.address == tx.sender => {
+                    s.checked_add(output.amount)
+                }
+                _ => None,
+            }
+        })
+    }
+}
+
+impl Ledger for UtxoSet {
+    fn unspent_outputs(&self, address: &str) -> Option<Vec<Utxo>> {
+        let utxos = self
+            .unspent
+            .iter()
+            .filter(|&(_, output)| output.address == address)
+            .map(|(out_point, output)| Utxo {
+                out_point: out_point.clone(),
+                amount: output.amount,
+            })
+            .collect();
+        Some(utxos)
+    }
+
+    fn balance(&self, address: &str) -> u64 {
+        self.unspent
+            .values()
+            .filter(|output| output.address == address)
+            .fold(0, |sum, output| sum.saturating_add(output.amount))
+    }
+
+    /// Check if all inputs are unspent outputs owned by the sender, without duplication,
+    /// and cover the amount plus fee.
+    fn validate_transaction(&self, tx: &Transaction) -> bool {
+        if tx.is_coinbase() {
+            return true;
+        }
+        let distinct: HashSet<&OutPoint> = tx.inputs.iter().collect();
+        if tx.inputs.is_empty() || distinct.len() != tx.inputs.len() {
+            debug!("validation failed: transaction spends no or duplicated outputs.");
+            return false;
+        }
+        let input_amount = match self.input_amount(tx) {
+            Some(amount) => amount,
+            None => {
+                debug!("validation failed: transaction spends unknown or spent outputs.");
+                return false;
+            }
+        };
+        match tx.amount.checked_add(tx.fee) {
+            Some(cost) if cost <= input_amount => true,
+            _ => {
+                debug!("validation failed: transaction overspends its inputs.");
+                false
+            }
+        }
+    }
+
+    fn apply_transaction(&mut self, tx: &Transaction) {
+        let tx_hash = hex::encode(tx.hash());
+        let outputs = self.outputs(tx);
+        let spent = tx
+            .inputs
+            .iter()
+            .map(|input| (input.clone(), self.unspent.remove(input).unwrap()))
+            .collect();
+        self.spent.insert(tx_hash.clone(), spent);
+        for (i, output) in outputs.into_iter().enumerate() {
+            let out_point = OutPoint {
+                tx_hash: tx_hash.clone(),
+                index: i as u32,
+            };
+            self.unspent.insert(out_point, output);
+        }
+    }
+
+    fn rollback_transaction(&mut self, tx: &Transaction) {
+        let tx_hash = hex::encode(tx.hash());
+        self.unspent
+            .retain(|out_point, _| out_point.tx_hash != tx_hash);
+        if let Some(spent) = self.spent.remove(&tx_hash) {
+            self.unspent.extend(spent);
+        }
+    }
+
+    fn clone_box(&self) -> Box<Ledger> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod test_utxo {
+    use super::*;
+    use blockchain::blockchain::{BlockBody, BlockHeader};
+    use ed25519_dalek::Keypair;
+    use rand::rngs::OsRng;
+
+    fn block_with(transactions: Vec<Transaction>) -> Block {
+        Block {
+            hash: Vec::new(),
+            header: BlockHeader {
+                index: 1,
+                time: String::new(),
+                pre_hash: Vec::new(),
+                merkle_root: Vec::new(),
+                difficulty: 0,
+                nonce: 0,
+            },
+            body: BlockBody { transactions },
+        }
+    }
+
+    #[test]
+    fn test_spend_and_double_spend() {
+        let keypair = Keypair::generate(&mut OsRng);
+        let owner = hex::encode(keypair.public.as_bytes());
+        let genesis = block_with(Vec::new());
+        let mut utxo_set = UtxoSet::new(
+            &genesis,
+            &[Allocation {
+                address: owner.clone(),
+                amount: 100,
+            }],
+        );
+        let premine = utxo_set.unspent_outputs(&owner).unwrap()[0]
+            .out_point
+            .clone();
+
+        let tx = Transaction::new_signed_from_inputs(&keypair, vec![premine.clone()], "bob", 30, 1);
+        let block = block_with(vec![Transaction::coinbase("miner", 51, 1), tx.clone()]);
+        assert!(utxo_set.apply_block(&block));
+        assert_eq!(69, utxo_set.balance(&owner));
+        assert_eq!(30, utxo_set.balance("bob"));
+        assert_eq!(51, utxo_set.balance("miner"));
+
+        // Double spending the premine output.
+        let double_spend =
+            Transaction::new_signed_from_inputs(&keypair, vec![premine], "eve", 30, 1);
+        assert!(!utxo_set.validate_transaction(&double_spend));
+
+        utxo_set.rollback_block(&block);
+        assert_eq!(100, utxo_set.balance(&owner));
+        assert_eq!(0, utxo_set.balance("bob"));
+        assert_eq!(0, utxo_set.balance("miner"));
+        assert!(utxo_set.validate_transaction(&double_spend));
+    }
+}
@@ -0,0 +1,368 @@
+//! The mempool module holds pending transactions until they're mined into blocks.
+
+use super::ledger::Ledger;
+use super::transaction::{Transaction, MAX_BLOCK_SIZE};
+use chrono::Utc;
+use hex;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// Maximum number of pending transactions, the lowest fee ones are evicted beyond it.
+pub const MAX_MEMPOOL_SIZE: usize = 5000;
+
+/// Seconds a transaction can stay in mempool before evicted.
+pub const MAX_TRANSACTION_AGE: i64 = 60 * 60;
+
+/// Number of recently seen transaction hashes remembered for gossip deduplication.
+pub const MAX_SEEN_TRANSACTIONS: usize = 10000;
+
+#[derive(Debug)]
+struct PendingTransaction {
+    tx: Transaction,
+    /// Unix timestamp when the transaction entered mempool.
+    received: i64,
+    /// Serialized size, computed once on entering mempool.
+    size: usize,
+}
+
+impl PendingTransaction {
+    fn new(tx: Transaction, received: i64) -> Self {
+        let size = tx.size();
+        PendingTransaction { tx, received, size }
+    }
+}
+
+/// Higher fee first, then the earlier received one, pending transactions are paired with their hashes.
+fn by_priority(a: &(&String, &PendingTransaction), b: &(&String, &PendingTransaction)) -> Ordering {
+    b.1.tx
+        .fee
+        .cmp(&a.1.tx.fee)
+        .then(a.1.received.cmp(&b.1.received))
+        .then(a.0.cmp(b.0))
+}
+
+#[derive(Debug)]
+pub struct Mempool {
+    /// Pending transactions keyed by hex encoded hash.
+    pending: HashMap<String, PendingTransaction>,
+    /// The ledger with all pending transactions applied, which new transactions are validated against.
+    /// Rebuilt lazily along with dropping inapplicable transactions, and kept up to date
+    /// on adding and evicting until the ledger changes, i.e. until the next `revalidate`.
+    pending_state: Option<Box<Ledger>>,
+    /// Hashes of recently accepted transactions, in arrival order.
+    seen: HashSet<String>,
+    seen_order: VecDeque<String>,
+    max_size: usize,
+    max_age: i64,
+}
+
+impl Mempool {
+    pub fn new() -> Self {
+        Mempool::with_limits(MAX_MEMPOOL_SIZE, MAX_TRANSACTION_AGE)
+    }
+
+    pub fn with_limits(max_size: usize, max_age: i64) -> Self {
+        Mempool {
+            pending: HashMap::new(),
+            pending_state: None,
+            seen: HashSet::new(),
+            seen_order: VecDeque::new(),
+            max_size,
+            max_age,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Check if the transaction has been accepted recently.
+    pub fn is_seen(&self, tx: &Transaction) -> bool {
+        self.seen.contains(&hex::encode(tx.hash()))
+    }
+
+    /// Remember the transaction as seen, return false if it has been seen recently.
+    /// Gossip stops at nodes which have seen the transaction, so it won't loop between peers.
+    /// Only accepted transactions are marked, so a rejected one can be retried once it becomes valid.
+    pub fn mark_seen(&mut self, tx: &Transaction) -> bool {
+        let hash = hex::encode(tx.hash());
+        if !self.seen.insert(hash.clone()) {
+            return false;
+        }
+        self.seen_order.push_back(hash);
+        if self.seen_order.len() > MAX_SEEN_TRANSACTIONS {
+            let oldest = self.seen_order.pop_front().unwrap();
+            self.seen.remove(&oldest);
+        }
+        true
+    }
+
+    /// Select transactions by priority which can be applied on top of the ledger,
+    /// until the serialized size reaches the limit.
+    /// Transactions depending on others, e.g. a higher nonce, are retried after those applied.
+    /// Return hashes of selected transactions, along with the ledger they're applied to.
+    fn select_within(&self, ledger: &Ledger, size_limit: usize) -> (Vec<String>, Box<Ledger>) {
+        let mut state = ledger.clone_box();
+        let mut remaining: Vec<_> = self.pending.iter().collect();
+        remaining.sort_by(by_priority);
+        let mut remaining: Vec<_> = remaining
+            .into_iter()
+            .map(|(hash, pending)| (hash, &pending.tx, pending.size))
+            .collect();
+        let mut selected = Vec::new();
+        let mut size = 0;
+        loop {
+            let before = selected.len();
+            remaining.retain(|&(hash, tx, tx_size)| {
+                if size + tx_size > size_limit || !state.validate_transaction(tx) {
+                    return true;
+                }
+                state.apply_transaction(tx);
+                selected.push(hash.clone());
+                size += tx_size;
+                false
+            });
+            if selected.len() == before || remaining.is_empty() {
+                return (selected, state);
+            }
+        }
+    }
+
+    /// Select transactions for the next block, up to the block size limit.
+    pub fn select(&self, ledger: &Ledger) -> Vec<Transaction> {
+        self.select_within(ledger, MAX_BLOCK_SIZE)
+            .0
+            .iter()
+            .map(|hash| self.pending[hash].tx.clone())
+            .collect()
+    }
+
+    /// Validate the transaction against the ledger with pending transactions applied,
+    /// and add it into mempool. Return false if the transaction is invalid, duplicated,
+    /// or evicted immediately due to the size limit.
+    pub fn add(&mut self, tx: Transaction, ledger: &Ledger) -> bool {
+        let hash = hex::encode(tx.hash());
+        if tx.is_coinbase() || !tx.verify() {
+            debug!("mempool rejects transaction {}: invalid signature.", hash);
+            return false;
+        }
+        if self.pending.contains_key(&hash) {
+            trace!("mempool ignores duplicated transaction {}.", hash);
+            return false;
+        }
+        self.evict_expired();
+        let full = self.pending.len() >= self.max_size;
+        if full
+            && self
+                .pending
+                .values()
+                .all(|pending| tx.fee <= pending.tx.fee)
+        {
+            trace!("mempool rejects transaction {}: pays too low fee.", hash);
+            return false;
+        }
+
+        if self.pending_state.is_none() {
+            self.revalidate(ledger);
+        }
+        if !self
+            .pending_state
+            .as_ref()
+            .unwrap()
+            .validate_transaction(&tx)
+        {
+            debug!(
+                "mempool rejects transaction {}: conflicts with state.",
+                hash
+            );
+            return false;
+        }
+        self.pending_state.as_mut().unwrap().apply_transaction(&tx);
+
+        self.pending.insert(
+            hash.clone(),
+            PendingTransaction::new(tx, Utc::now().timestamp()),
+        );
+        while self.pending.len() > self.max_size {
+            self.evict_lowest();
+        }
+        self.pending.contains_key(&hash)
+    }
+
+    fn evict_expired(&mut self) {
+        let deadline = Utc::now().timestamp() - self.max_age;
+        let before = self.pending.len();
+        self.pending
+            .retain(|_, pending| pending.received >= deadline);
+        if self.pending.len() != before {
+            self.pending_state = None;
+        }
+    }
+
+    /// Evict the lowest priority transaction which no other pending transaction may depend on,
+    /// i.e. neither a later nonce of its sender, a transaction spending its outputs,
+    /// nor one sent by its recipient. It's rolled back from the pending state.
+    fn evict_lowest(&mut self) {
+        let mut last_nonces: HashMap<&str, u64> = HashMap::new();
+        let mut senders = HashSet::new();
+        let mut spent = HashSet::new();
+        for pending in self.pending.values() {
+            let tx = &pending.tx;
+            senders.insert(tx.sender.as_str());
+            spent.extend(tx.inputs.iter().map(|input| input.tx_hash.as_str()));
+            if tx.inputs.is_empty() {
+                let nonce = last_nonces.entry(tx.sender.as_str()).or_insert(tx.nonce);
+                *nonce = (*nonce).max(tx.nonce);
+            }
+        }
+        let is_leaf = |hash: &str, tx: &Transaction| {
+            !spent.contains(hash)
+                && !senders.contains(tx.recipient.as_str())
+                && (!tx.inputs.is_empty() || last_nonces[tx.sender.as_str()] == tx.nonce)
+        };
+        let lowest = |leaf_only: bool| {
+            self.pending
+                .iter()
+                .filter(|&(hash, pending)| !leaf_only || is_leaf(hash, &pending.tx))
+                .max_by(by_priority)
+                .map(|(hash, _)| hash.clone())
+        };
+        let (hash, leaf) = match lowest(true) {
+            Some(hash) => (hash, true),
+            None => (lowest(false).unwrap(), false),
+        };
+        trace!("mempool evicts transaction {} due to size limit.", hash);
+        let evicted = self.pending.remove(&hash).unwrap();
+        match self.pending_state {
+            Some(ref mut state) if leaf => state.rollback_transaction(&evicted.tx),
+            _ => self.pending_state = None,
+        }
+    }
+
+    /// Re-validate pending transactions after the ledger changed, e.g. new blocks or reorgs.
+    /// Transactions already mined or no longer applicable are dropped.
+    pub fn revalidate(&mut self, ledger: &Ledger) {
+        self.evict_expired();
+        let (selected, state) = self.select_within(ledger, usize::max_value());
+        let valid: HashSet<String> = selected.into_iter().collect();
+        self.pending.retain(|hash, _| valid.contains(hash));
+        self.pending_state = Some(state);
+    }
+
+    /// Put transactions of rolled back blocks back into mempool, coinbases are dropped.
+    /// Should be followed by `revalidate` against the new ledger.
+    pub fn restore(&mut self, transactions: Vec<Transaction>) {
+        let now = Utc::now().timestamp();
+        self.pending_state = None;
+        for tx in transactions.into_iter().filter(|tx| !tx.is_coinbase()) {
+            self.pending
+                .entry(hex::encode(tx.hash()))
+                .or_insert_with(|| PendingTransaction::new(tx, now));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_mempool {
+    use super::*;
+    use blockchain::genesis::Allocation;
+    use blockchain::ledger::account::AccountLedger;
+    use ed25519_dalek::Keypair;
+    use rand::rngs::OsRng;
+
+    fn funded_ledger(keypair: &Keypair, amount: u64) -> AccountLedger {
+        AccountLedger::new(&[Allocation {
+            address: hex::encode(keypair.public.as_bytes()),
+            amount,
+        }])
+    }
+
+    #[test]
+    fn test_select_by_fee_and_nonce() {
+        let keypair = Keypair::generate(&mut OsRng);
+        let other = Keypair::generate(&mut OsRng);
+        let mut ledger = funded_ledger(&keypair, 100);
+        ledger.apply_transaction(&Transaction::coinbase(
+            hex::encode(other.public.as_bytes()),
+            100,
+            0,
+        ));
+        let mut mempool = Mempool::new();
+
+        let first = Transaction::new_signed(&keypair, "bob", 10, 1, 0);
+        let second = Transaction::new_signed(&keypair, "bob", 10, 5, 1);
+        let other_tx = Transaction::new_signed(&other, "bob", 10, 3, 0);
+        assert!(mempool.add(first.clone(), &ledger));
+        assert!(mempool.add(second.clone(), &ledger));
+        assert!(mempool.add(other_tx.clone(), &ledger));
+        assert!(!mempool.add(first.clone(), &ledger));
+
+        // The second one pays more but depends on the first one.
+        assert_eq!(vec![other_tx, first, second], mempool.select(&ledger));
+    }
+
+    #[test]
+    fn test_reject_overspend_and_evict() {
+        let keypair = Keypair::generate(&mut OsRng);
+        let other = Keypair::generate(&mut OsRng);
+        let mut ledger = funded_ledger(&keypair, 100);
+        ledger.apply_transaction(&Transaction::coinbase(
+            hex::encode(other.public.as_bytes()),
+            100,
+            0,
+        ));
+        let mut mempool = Mempool::with_limits(1, MAX_TRANSACTION_AGE);
+
+        assert!(mempool.add(Transaction::new_signed(&keypair, "bob", 60, 1, 0), &ledger));
+        assert!(!mempool.add(Transaction::new_signed(&keypair, "bob", 60, 1, 1), &ledger));
+        // Evicted immediately, since it depends on the pending one.
+        assert!(!mempool.add(Transaction::new_signed(&keypair, "bob", 1, 2, 1), &ledger));
+        // Evict the pending one paying lower fee.
+        let higher_fee = Transaction::new_signed(&other, "bob", 1, 2, 0);
+        assert!(mempool.add(higher_fee.clone(), &ledger));
+        assert_eq!(vec![higher_fee], mempool.select(&ledger));
+    }
+
+    #[test]
+    fn test_reject_low_fee_when_full() {
+        let keypair = Keypair::generate(&mut OsRng);
+        let ledger = funded_ledger(&keypair, 100);
+        let mut mempool = Mempool::with_limits(2, MAX_TRANSACTION_AGE);
+        let first = Transaction::new_signed(&keypair, "bob", 1, 3, 0);
+        let second = Transaction::new_signed(&keypair, "bob", 1, 2, 1);
+        assert!(mempool.add(first.clone(), &ledger));
+        assert!(mempool.add(second.clone(), &ledger));
+
+        assert!(!mempool.add(Transaction::new_signed(&keypair, "bob", 1, 2, 2), &ledger));
+        assert_eq!(vec![first, second], mempool.select(&ledger));
+    }
+
+    #[test]
+    fn test_mark_seen() {
+        let keypair = Keypair::generate(&mut OsRng);
+        let mut mempool = Mempool::new();
+        let tx = Transaction::new_signed(&keypair, "bob", 10, 1, 0);
+        assert!(!mempool.is_seen(&tx));
+        assert!(mempool.mark_seen(&tx));
+        assert!(mempool.is_seen(&tx));
+        assert!(!mempool.mark_seen(&tx));
+    }
+
+    #[test]
+    fn test_revalidate_after_block() {
+        let keypair = Keypair::generate(&mut OsRng);
+        let mut ledger = funded_ledger(&keypair, 100);
+        let mut mempool = Mempool::new();
+        let tx = Transaction::new_signed(&keypair, "bob", 10, 1, 0);
+        assert!(mempool.add(tx.clone(), &ledger));
+
+        ledger.apply_transaction(&tx);
+        mempool.revalidate(&ledger);
+        assert_eq!(0, mempool.len());
+
+        ledger.rollback_transaction(&tx);
+        mempool.restore(vec![tx]);
+        mempool.revalidate(&ledger);
+        assert_eq!(1, mempool.len());
+    }
+}
@@ -0,0 +1,148 @@
+//! The merkle module builds the merkle tree over block transactions,
+//! and the inclusion proofs of a single transaction.
+//!
+//! Leaves and inner nodes are hashed with different prefixes, hence an inner node can't be
+//! presented as a leaf. A node without sibling is promoted to the upper level as is.
+
+use hex;
+use sha3::{Digest, Sha3_256};
+
+const LEAF_PREFIX: u8 = 0;
+const NODE_PREFIX: u8 = 1;
+
+fn hash_leaf(leaf: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha3_256::default();
+    hasher.input(&[LEAF_PREFIX]);
+    hasher.input(leaf);
+    hasher.result().as_slice().to_vec()
+}
+
+fn hash_node(left: &[u8], right: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha3_256::default();
+    hasher.input(&[NODE_PREFIX]);
+    hasher.input(left);
+    hasher.input(right);
+    hasher.result().as_slice().to_vec()
+}
+
+/// Hash pairs of nodes into the upper level.
+fn next_level(level: &[Vec<u8>]) -> Vec<Vec<u8>> {
+    level
+        .chunks(2)
+        .map(|pair| {
+            if pair.len() == 2 {
+                hash_node(&pair[0], &pair[1])
+            } else {
+                pair[0].clone()
+            }
+        })
+        .collect()
+}
+
+/// Calculate the merkle root of leaves, the root of no leaves is the hash of empty input.
+pub fn merkle_root(leaves: &[Vec<u8>]) -> Vec<u8> {
+    if leaves.is_empty() {
+        return Sha3_256::default().result().as_slice().to_vec();
+    }
+    let mut level: Vec<Vec<u8>> = leaves.iter().map(|leaf| hash_leaf(leaf)).collect();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level.pop().unwrap()
+}
+
+/// A sibling hash on the path from leaf to root.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProofStep {
+    /// Hex encoded hash of the sibling.
+    pub hash: String,
+    /// Whether the sibling is on the left side.
+    pub is_left: bool,
+}
+
+/// The merkle inclusion proof of a leaf.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MerkleProof {
+    pub leaf_index: usize,
+    pub steps: Vec<ProofStep>,
+}
+
+/// Build the inclusion proof of the leaf at index, return None if out of range.
+pub fn merkle_proof(leaves: &[Vec<u8>], leaf_index: usize) -> Option<MerkleProof> {
+    if leaf_index >= leaves.len() {
+        return None;
+    }
+    let mut steps = Vec::new();
+    let mut level: Vec<Vec<u8>> = leaves.iter().map(|leaf| hash_leaf(leaf)).collect();
+    let mut index = leaf_index;
+    while level.len() > 1 {
+        let sibling = index ^ 1;
+        // The last node without sibling is promoted, no step for it.
+        if sibling < level.len() {
+            steps.push(ProofStep {
+                hash: hex::encode(&level[sibling]),
+                is_left: sibling < index,
+            });
+        }
+        level = next_level(&level);
+        index /= 2;
+    }
+    Some(MerkleProof { leaf_index, steps })
+}
+
+/// Verify the leaf is included in the tree of root, by the proof.
+#[cfg(test)]
+pub fn verify_proof(leaf: &[u8], proof: &MerkleProof, root: &[u8]) -> bool {
+    let mut hash = hash_leaf(leaf);
+    for step in proof.steps.iter() {
+        let sibling = match hex::decode(&step.hash) {
+            Ok(sibling) => sibling,
+            Err(_) => return false,
+        };
+        hash = if step.is_left {
+            hash_node(&sibling, &hash)
+        } else {
+            hash_node(&hash, &sibling)
+        };
+    }
+    hash == root
+}
+
+#[cfg(test)]
+mod test_merkle {
+    use super::*;
+
+    fn leaves(count: u8) -> Vec<Vec<u8>> {
+        (0..count).map(|i| vec![i]).collect()
+    }
+
+    #[test]
+    fn test_proofs_of_all_leaves() {
+        for count in 1..8 {
+            let leaves = leaves(count);
+            let root = merkle_root(&leaves);
+            for (i, leaf) in leaves.iter().enumerate() {
+                let proof = merkle_proof(&leaves, i).unwrap();
+                assert!(verify_proof(leaf, &proof, &root));
+            }
+            assert!(merkle_proof(&leaves, leaves.len()).is_none());
+        }
+    }
+
+    #[test]
+    fn test_reject_invalid_proof() {
+        let leaves = leaves(5);
+        let root = merkle_root(&leaves);
+        let proof = merkle_proof(&leaves, 2).unwrap();
+        assert!(!verify_proof(&[9], &proof, &root));
+        assert!(!verify_proof(
+            &leaves[2],
+            &proof,
+            &merkle_root(&leaves[..4])
+        ));
+
+        let mut tampered = proof.clone();
+        tampered.steps[0].is_left = !tampered.steps[0].is_left;
+        assert!(!verify_proof(&leaves[2], &tampered, &root));
+    }
+}
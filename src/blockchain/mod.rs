@@ -3,10 +3,23 @@
 pub mod blockchain;
 pub mod blockchain_external;
 pub mod blockchain_sync;
+pub mod difficulty;
+pub mod fork_choice;
+pub mod genesis;
+pub mod headers_sync;
+pub mod ledger;
+pub mod mempool;
+pub mod merkle;
+pub mod orphan;
+pub mod pull_sync;
+pub mod snapshot;
+pub mod store;
+pub mod transaction;
 
 use self::blockchain::BlockChain;
 use self::blockchain_external::BlockChainExternalAPIs;
 use self::blockchain_sync::BlockChainSyncAPIs;
+use self::mempool::Mempool;
 use gotham::handler::HandlerFuture;
 use gotham::state::State;
 use hyper::Response;
@@ -19,28 +32,94 @@ pub struct BlockChainAPIs {
 }
 
 impl BlockChainAPIs {
-    pub fn new(peer_apis: Arc<PeerAPIs>) -> Self {
-        let block_chain = Arc::new(RwLock::new(BlockChain::new()));
+    pub fn new(block_chain: Arc<RwLock<BlockChain>>, peer_apis: Arc<PeerAPIs>) -> Self {
+        let mempool = Arc::new(RwLock::new(Mempool::new()));
 
         BlockChainAPIs {
             blockchain_external_apis: BlockChainExternalAPIs::new(
                 block_chain.clone(),
+                mempool.clone(),
                 peer_apis.clone(),
             ),
-            blockchain_sync_apis: BlockChainSyncAPIs::new(block_chain, peer_apis.clone()),
+            blockchain_sync_apis: BlockChainSyncAPIs::new(block_chain, mempool, peer_apis.clone()),
         }
     }
 
+    /// Re-export sync apis spawn pull sync method.
+    pub fn spawn_pull_sync(&self) {
+        self.blockchain_sync_apis.spawn_pull_sync()
+    }
+
     /// Re-export external apis list block chain method.
     pub fn list_block_chain(&self, state: State) -> (State, Response) {
         self.blockchain_external_apis.list_block_chain(state)
     }
 
+    /// Re-export external apis list blocks method.
+    pub fn list_blocks(&self, state: State) -> (State, Response) {
+        self.blockchain_external_apis.list_blocks(state)
+    }
+
+    /// Re-export external apis list reorgs method.
+    pub fn list_reorgs(&self, state: State) -> (State, Response) {
+        self.blockchain_external_apis.list_reorgs(state)
+    }
+
+    /// Re-export external apis get headers method.
+    pub fn get_headers(&self, state: State) -> (State, Response) {
+        self.blockchain_external_apis.get_headers(state)
+    }
+
+    /// Re-export external apis get header method.
+    pub fn get_header(&self, state: State) -> (State, Response) {
+        self.blockchain_external_apis.get_header(state)
+    }
+
+    /// Re-export external apis get block by hash method.
+    pub fn get_block_by_hash(&self, state: State) -> (State, Response) {
+        self.blockchain_external_apis.get_block_by_hash(state)
+    }
+
+    /// Re-export external apis get block by height method.
+    pub fn get_block_by_height(&self, state: State) -> (State, Response) {
+        self.blockchain_external_apis.get_block_by_height(state)
+    }
+
+    /// Re-export external apis get body method.
+    pub fn get_body(&self, state: State) -> (State, Response) {
+        self.blockchain_external_apis.get_body(state)
+    }
+
+    /// Re-export external apis submit transaction method.
+    pub fn submit_transaction(&self, state: State) -> Box<HandlerFuture> {
+        self.blockchain_external_apis.submit_transaction(state)
+    }
+
     /// Re-export external apis mine block method.
     pub fn mine_block(&self, state: State) -> Box<HandlerFuture> {
         self.blockchain_external_apis.mine_block(state)
     }
 
+    /// Re-export external apis get balance method.
+    pub fn get_balance(&self, state: State) -> (State, Response) {
+        self.blockchain_external_apis.get_balance(state)
+    }
+
+    /// Re-export external apis get account method.
+    pub fn get_account(&self, state: State) -> (State, Response) {
+        self.blockchain_external_apis.get_account(state)
+    }
+
+    /// Re-export external apis get unspent outputs method.
+    pub fn get_unspent_outputs(&self, state: State) -> (State, Response) {
+        self.blockchain_external_apis.get_unspent_outputs(state)
+    }
+
+    /// Re-export external apis get transaction proof method.
+    pub fn get_transaction_proof(&self, state: State) -> (State, Response) {
+        self.blockchain_external_apis.get_transaction_proof(state)
+    }
+
     /// Re-export sync apis response latest block method.
     pub fn response_latest_block(&self, state: State) -> (State, Response) {
         self.blockchain_sync_apis.response_latest_block(state)
@@ -55,4 +134,14 @@ impl BlockChainAPIs {
     pub fn publish_block_handler(&self, state: State) -> Box<HandlerFuture> {
         self.blockchain_sync_apis.publish_block_handler(state)
     }
+
+    /// Re-export sync apis headers first sync method.
+    pub fn sync_with_peers(&self, state: State) -> Box<HandlerFuture> {
+        self.blockchain_sync_apis.sync_with_peers(state)
+    }
+
+    /// Re-export sync apis publish transaction handler method.
+    pub fn publish_transaction_handler(&self, state: State) -> Box<HandlerFuture> {
+        self.blockchain_sync_apis.publish_transaction_handler(state)
+    }
 }
@@ -0,0 +1,203 @@
+//! The orphan module keeps published blocks whose parent is unknown yet,
+//! until their ancestors arrive and they can be connected to own chain.
+
+use super::blockchain::{hash_matches_difficulty, Block, BlockChain};
+use chrono::Utc;
+use std::collections::HashMap;
+
+/// Maximum number of orphan blocks, the oldest ones are evicted beyond it.
+pub const MAX_ORPHAN_BLOCKS: usize = 100;
+
+/// Seconds an orphan block can stay in the pool before evicted.
+pub const MAX_ORPHAN_AGE: i64 = 10 * 60;
+
+#[derive(Debug)]
+struct Orphan {
+    block: Block,
+    /// Unix timestamp when the block entered the pool.
+    received: i64,
+}
+
+#[derive(Debug)]
+pub struct OrphanPool {
+    /// Orphan blocks keyed by block hash.
+    orphans: HashMap<Vec<u8>, Orphan>,
+    max_size: usize,
+    max_age: i64,
+}
+
+impl OrphanPool {
+    pub fn new() -> Self {
+        OrphanPool::with_limits(MAX_ORPHAN_BLOCKS, MAX_ORPHAN_AGE)
+    }
+
+    pub fn with_limits(max_size: usize, max_age: i64) -> Self {
+        OrphanPool {
+            orphans: HashMap::new(),
+            max_size,
+            max_age,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.orphans.len()
+    }
+
+    /// Keep the block until its parent arrives, return false if it's already kept,
+    /// or its hash doesn't match the header or the difficulty, so the pool can't be flooded for free.
+    /// The claimed difficulty can't be lower than own tip's, otherwise it costs nothing to forge.
+    /// The oldest orphan is evicted if the pool is full.
+    pub fn add(&mut self, block: Block, own_chain: &BlockChain) -> bool {
+        if self.orphans.contains_key(&block.hash) {
+            return false;
+        }
+        let min_difficulty = own_chain.get_latest().unwrap().header.difficulty;
+        if block.hash != block.header.hash()
+            || block.header.difficulty < min_difficulty
+            || !hash_matches_difficulty(&block.hash, block.header.difficulty)
+        {
+            debug!("drop orphan block #{}: invalid hash.", block.header.index);
+            return false;
+        }
+        self.evict_expired();
+        if self.orphans.len() >= self.max_size {
+            let oldest = self
+                .orphans
+                .iter()
+                .min_by_key(|&(_, orphan)| orphan.received)
+                .map(|(hash, _)| hash.clone());
+            if let Some(hash) = oldest {
+                self.orphans.remove(&hash);
+            }
+        }
+        trace!("keep orphan block #{}.", block.header.index);
+        self.orphans.insert(
+            block.hash.clone(),
+            Orphan {
+                block,
+                received: Utc::now().timestamp(),
+            },
+        );
+        true
+    }
+
+    /// Evict orphans staying longer than the max age.
+    pub fn evict_expired(&mut self) {
+        let deadline = Utc::now().timestamp() - self.max_age;
+        self.orphans.retain(|_, orphan| orphan.received > deadline);
+    }
+
+    /// Remove and return orphans whose parent is in own chain, ordered by index.
+    pub fn take_connectable(&mut self, own_chain: &BlockChain) -> Vec<Block> {
+        let hashes: Vec<Vec<u8>> = self
+            .orphans
+            .iter()
+            .filter(|&(_, orphan)| {
+                let header = &orphan.block.header;
+                header.index > 0
+                    && own_chain
+                        .get_block(header.index - 1)
+                        .map_or(false, |parent| parent.hash == header.pre_hash)
+            })
+            .map(|(hash, _)| hash.clone())
+            .collect();
+        let mut blocks: Vec<Block> = hashes
+            .iter()
+            .filter_map(|hash| self.orphans.remove(hash))
+            .map(|orphan| orphan.block)
+            .collect();
+        blocks.sort_by_key(|block| block.header.index);
+        blocks
+    }
+
+    /// Remove and return an orphan whose parent is the block of hash.
+    pub fn take_child(&mut self, hash: &[u8]) -> Option<Block> {
+        let child = self
+            .orphans
+            .iter()
+            .find(|&(_, orphan)| orphan.block.header.pre_hash == hash)
+            .map(|(child, _)| child.clone())?;
+        self.orphans.remove(&child).map(|orphan| orphan.block)
+    }
+}
+
+#[cfg(test)]
+mod test_orphan {
+    use super::super::genesis::GenesisConfig;
+    use super::super::ledger::LedgerMode;
+    use super::*;
+
+    #[test]
+    fn test_take_connectable_and_children() {
+        let genesis = GenesisConfig::default();
+        let own_chain = BlockChain::new(&genesis, LedgerMode::Account);
+        let mut peer_chain = BlockChain::new(&genesis, LedgerMode::Account);
+        let mut blocks = Vec::new();
+        for _ in 0..3 {
+            let block = peer_chain.generate_next_block(Vec::new());
+            assert!(peer_chain.add_new_block(block.clone()));
+            blocks.push(block);
+        }
+
+        let mut orphans = OrphanPool::new();
+        assert!(orphans.add(blocks[2].clone(), &own_chain));
+        assert!(!orphans.add(blocks[2].clone(), &own_chain));
+        assert!(orphans.take_connectable(&own_chain).is_empty());
+        assert!(orphans.add(blocks[1].clone(), &own_chain));
+        assert!(orphans.add(blocks[0].clone(), &own_chain));
+
+        let connectable = orphans.take_connectable(&own_chain);
+        assert_eq!(1, connectable.len());
+        assert_eq!(blocks[0].hash, connectable[0].hash);
+        assert_eq!(
+            blocks[1].hash,
+            orphans.take_child(&blocks[0].hash).unwrap().hash
+        );
+        assert_eq!(1, orphans.len());
+    }
+
+    #[test]
+    fn test_evict_oldest_and_expired() {
+        let genesis = GenesisConfig::default();
+        let own_chain = BlockChain::new(&genesis, LedgerMode::Account);
+        let mut peer_chain = BlockChain::new(&genesis, LedgerMode::Account);
+        let mut blocks = Vec::new();
+        for _ in 0..3 {
+            let block = peer_chain.generate_next_block(Vec::new());
+            assert!(peer_chain.add_new_block(block.clone()));
+            blocks.push(block);
+        }
+
+        let mut orphans = OrphanPool::with_limits(2, MAX_ORPHAN_AGE);
+        for block in blocks.iter() {
+            assert!(orphans.add(block.clone(), &own_chain));
+        }
+        assert_eq!(2, orphans.len());
+
+        let mut orphans = OrphanPool::with_limits(MAX_ORPHAN_BLOCKS, -1);
+        assert!(orphans.add(blocks[0].clone(), &own_chain));
+        orphans.evict_expired();
+        assert_eq!(0, orphans.len());
+    }
+
+    #[test]
+    fn test_reject_invalid_hash() {
+        let genesis = GenesisConfig::default();
+        let own_chain = BlockChain::new(&genesis, LedgerMode::Account);
+        let block = own_chain.generate_next_block(Vec::new());
+        let mut orphans = OrphanPool::new();
+
+        let mut forged = block.clone();
+        forged.header.nonce += 1;
+        assert!(!orphans.add(forged.clone(), &own_chain));
+        forged.header.difficulty = 255;
+        forged.hash = forged.header.hash();
+        assert!(!orphans.add(forged.clone(), &own_chain));
+        // Any hash matches difficulty 0.
+        forged.header.difficulty = 0;
+        forged.hash = forged.header.hash();
+        assert!(!orphans.add(forged, &own_chain));
+        assert_eq!(0, orphans.len());
+        assert!(orphans.add(block, &own_chain));
+    }
+}
@@ -0,0 +1,123 @@
+//! The pull sync module periodically queries the latest block of peers in background,
+//! and syncs from a peer whose latest block is unknown, so an offline node catches up.
+
+use super::blockchain::{Block, BlockChain};
+use super::blockchain_sync::SyncBlockMessage;
+use super::headers_sync::HeadersSync;
+use futures::{future, stream, Future, Stream};
+use hyper;
+use peers::ban::Misbehavior;
+use peers::{is_timeout, with_timeout, PeerAPIs};
+use serde_json;
+use std::io::{self, ErrorKind};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+use tokio_core::reactor::{Core, Handle, Interval};
+
+/// Seconds between two rounds of pulling, if not configured.
+pub const DEFAULT_PULL_SYNC_INTERVAL: u64 = 30;
+
+/// Seconds waiting for a peer to respond its latest block.
+const QUERY_TIMEOUT: u64 = 10;
+
+#[derive(Clone)]
+pub struct PullSync {
+    block_chain: Arc<RwLock<BlockChain>>,
+    peer_apis: Arc<PeerAPIs>,
+    headers_sync: Arc<HeadersSync>,
+}
+
+impl PullSync {
+    pub fn new(
+        block_chain: Arc<RwLock<BlockChain>>,
+        peer_apis: Arc<PeerAPIs>,
+        headers_sync: Arc<HeadersSync>,
+    ) -> Self {
+        PullSync {
+            block_chain,
+            peer_apis,
+            headers_sync,
+        }
+    }
+
+    /// Spawn a thread running its own reactor, which pulls from all peers every interval.
+    pub fn spawn(self, interval: Duration) {
+        thread::spawn(move || {
+            let mut core = Core::new().expect("failed to create the pull sync reactor.");
+            let handle = core.handle();
+            let ticks = Interval::new(interval, &handle).expect("failed to create the interval.");
+            let pull_future = ticks.for_each(|_| self.pull(handle.clone()).then(|_| Ok(())));
+            core.run(pull_future).expect("pull sync stopped.");
+        });
+    }
+
+    /// Pull from all peers one by one, so they don't interfere in the shared sync progress.
+    /// Failures of a peer don't affect others.
+    fn pull(&self, handle: Handle) -> Box<Future<Item = (), Error = hyper::Error>> {
+        let pull_sync = self.clone();
+        let peers = self.peer_apis.peer_addresses();
+        Box::new(stream::iter_ok(peers).for_each(move |peer| {
+            pull_sync
+                .pull_from(handle.clone(), peer.clone())
+                .then(move |result| {
+                    if let Err(err) = result {
+                        debug!("pull from {} failed: {}", peer, err);
+                    }
+                    Ok(())
+                })
+        }))
+    }
+
+    /// Query the latest block of the peer, and sync from it by headers first sync
+    /// unless the block is already in own chain. Headers sync switches to the peer's chain
+    /// only if it has more cumulative work.
+    /// The peer is penalized if it responses a malformed block or times out.
+    fn pull_from(
+        &self,
+        handle: Handle,
+        peer: String,
+    ) -> Box<Future<Item = (), Error = hyper::Error>> {
+        let query_future = self
+            .peer_apis
+            .query(&handle, &peer, SyncBlockMessage::QueryLatest);
+        let timeout_peer_apis = self.peer_apis.clone();
+        let timeout_peer = peer.clone();
+        let latest_future =
+            with_timeout(&handle, query_future, QUERY_TIMEOUT).map_err(move |err| {
+                if is_timeout(&err) {
+                    timeout_peer_apis.penalize(&timeout_peer, Misbehavior::Timeout);
+                }
+                err
+            });
+
+        let cloned_chain = self.block_chain.clone();
+        let cloned_peer_apis = self.peer_apis.clone();
+        let cloned_headers_sync = self.headers_sync.clone();
+        Box::new(latest_future.and_then(move |content| {
+            let latest: Block = match serde_json::from_slice(&content) {
+                Ok(block) => block,
+                Err(_) => {
+                    cloned_peer_apis.penalize(&peer, Misbehavior::MalformedMessage);
+                    return future::Either::A(future::err(
+                        io::Error::new(ErrorKind::InvalidData, "parsing block data error.").into(),
+                    ));
+                }
+            };
+            let known = cloned_chain
+                .read()
+                .unwrap()
+                .get_block_by_hash(&latest.hash)
+                .is_some();
+            if known {
+                return future::Either::A(future::ok(()));
+            }
+            trace!(
+                "{} has unknown block #{}, sync from it.",
+                peer,
+                latest.header.index
+            );
+            future::Either::B(cloned_headers_sync.sync_from(handle, peer).map(|_| ()))
+        }))
+    }
+}
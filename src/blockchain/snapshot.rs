@@ -0,0 +1,90 @@
+//! The snapshot module exports own chain to a file, and imports a chain from it,
+//! so a new node can be seeded, or a captured chain can be replayed.
+//! Snapshots are the json chain format used between peers.
+
+use super::blockchain::{replace_to_new_chain, BlockChain};
+use super::fork_choice::ForkChoice;
+use serde_json;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, ErrorKind, Write};
+use std::path::Path;
+
+/// Write all blocks of the chain to the file.
+pub fn export_chain<P: AsRef<Path>>(block_chain: &BlockChain, path: P) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer(&mut writer, block_chain)?;
+    writer.flush()
+}
+
+/// Validate the chain in the file, and switch own chain to it if it's preferred by the fork choice.
+/// Return the number of blocks applied to own chain.
+pub fn import_chain<P: AsRef<Path>>(
+    block_chain: &mut BlockChain,
+    path: P,
+    fork_choice: &ForkChoice,
+) -> io::Result<usize> {
+    let imported: BlockChain = serde_json::from_reader(BufReader::new(File::open(path)?))
+        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+    if imported.len() == 0 || imported.genesis_hash() != block_chain.genesis_hash() {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "genesis block mismatch.",
+        ));
+    }
+    match replace_to_new_chain(block_chain, imported, fork_choice) {
+        Some(ref reorg) if block_chain.apply_reorg(reorg) => Ok(reorg.applied.len()),
+        _ => Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "invalid or less preferred chain.",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test_snapshot {
+    use super::super::blockchain::Block;
+    use super::super::genesis::GenesisConfig;
+    use super::super::ledger::LedgerMode;
+    use super::*;
+    use chrono::Utc;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn test_export_and_import() {
+        let path = env::temp_dir().join(format!(
+            "bam-chain-snapshot-{}",
+            Utc::now().timestamp_nanos()
+        ));
+        let genesis = GenesisConfig::default();
+        let mut block_chain = BlockChain::new(&genesis, LedgerMode::Account);
+        for _ in 0..3 {
+            let block = block_chain.generate_next_block(Vec::new());
+            assert!(block_chain.add_new_block(block));
+        }
+        export_chain(&block_chain, &path).unwrap();
+
+        let mut fresh_chain = BlockChain::new(&genesis, LedgerMode::Account);
+        assert_eq!(
+            3,
+            import_chain(&mut fresh_chain, &path, &ForkChoice::default()).unwrap()
+        );
+        assert_eq!(
+            block_chain.get_latest().unwrap().hash,
+            fresh_chain.get_latest().unwrap().hash
+        );
+        // The same chain is not preferred over itself.
+        assert!(import_chain(&mut fresh_chain, &path, &ForkChoice::default()).is_err());
+
+        // A tampered block fails the validation.
+        let mut blocks: serde_json::Value = serde_json::from_slice(&block_chain.to_vec()).unwrap();
+        let mut tampered: Block = serde_json::from_value(blocks["chain"][2].clone()).unwrap();
+        tampered.header.nonce += 1;
+        blocks["chain"][2] = serde_json::to_value(&tampered).unwrap();
+        fs::write(&path, serde_json::to_vec(&blocks).unwrap()).unwrap();
+        let mut fresh_chain = BlockChain::new(&genesis, LedgerMode::Account);
+        assert!(import_chain(&mut fresh_chain, &path, &ForkChoice::default()).is_err());
+        assert_eq!(1, fresh_chain.len());
+        fs::remove_file(&path).unwrap();
+    }
+}
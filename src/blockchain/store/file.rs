@@ -0,0 +1,279 @@
+//! The file module stores blocks in an append-only log file, along with an index file.
+//! All blocks are loaded when the store is opened, and kept in memory for reads.
+//!
+//! Each record of the log is the length and the sha3 checksum of a serialized block,
+//! followed by the serialized block. The index holds the offset of every record.
+//! A record is synced to disk before its index entry, so a crash in between leaves
+//! an unindexed tail, which is cut on load, as well as torn or corrupted records.
+
+use super::BlockStore;
+use blockchain::blockchain::Block;
+use serde_json;
+use sha3::{Digest, Sha3_256};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const LOG_FILE: &str = "blocks.log";
+const INDEX_FILE: &str = "blocks.idx";
+
+/// Bytes of the length and the checksum before a serialized block.
+const RECORD_HEADER_SIZE: usize = 4 + 32;
+const INDEX_ENTRY_SIZE: usize = 8;
+
+fn checksum(bytes: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha3_256::default();
+    hasher.input(bytes);
+    hasher.result().as_slice().to_vec()
+}
+
+/// Parse the record at offset of the log, return the block and the end of the record.
+/// Return None if the record is torn or corrupted.
+fn read_record(log: &[u8], offset: usize) -> Option<(Block, usize)> {
+    let header = log.get(offset..offset + RECORD_HEADER_SIZE)?;
+    let mut len_bytes = [0u8; 4];
+    len_bytes.copy_from_slice(&header[..4]);
+    let end = offset + RECORD_HEADER_SIZE + u32::from_le_bytes(len_bytes) as usize;
+    let content = log.get(offset + RECORD_HEADER_SIZE..end)?;
+    if checksum(content) != &header[4..] {
+        return None;
+    }
+    serde_json::from_slice(content)
+        .ok()
+        .map(|block| (block, end))
+}
+
+fn open_file(path: &Path) -> io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(path)
+}
+
+#[derive(Debug)]
+pub struct FileStore {
+    log: File,
+    index: File,
+    blocks: Vec<Block>,
+    /// Offsets of records in the log.
+    offsets: Vec<u64>,
+    /// Bytes of the log covered by records.
+    log_len: u64,
+}
+
+impl FileStore {
+    /// Open the store in the directory, create it if not exists.
+    /// Stored blocks are loaded, a damaged tail of the files is cut.
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        let mut store = FileStore {
+            log: open_file(&dir.join(LOG_FILE))?,
+            index: open_file(&dir.join(INDEX_FILE))?,
+            blocks: Vec::new(),
+            offsets: Vec::new(),
+            log_len: 0,
+        };
+        store.load(true)?;
+        Ok(store)
+    }
+
+    /// Open the existing store in the directory without modifying it,
+    /// e.g. while a node is writing to it. A damaged tail is skipped instead of cut.
+    pub fn open_read_only<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        let dir = dir.as_ref();
+        let mut store = FileStore {
+            log: File::open(dir.join(LOG_FILE))?,
+            index: File::open(dir.join(INDEX_FILE))?,
+            blocks: Vec::new(),
+            offsets: Vec::new(),
+            log_len: 0,
+        };
+        store.load(false)?;
+        Ok(store)
+    }
+
+    /// Cut both files to the records loaded, and sync to disk.
+    fn cut(&mut self) -> io::Result<()> {
+        self.log.set_len(self.log_len)?;
+        self.index
+            .set_len((self.offsets.len() * INDEX_ENTRY_SIZE) as u64)?;
+        self.log.sync_all()?;
+        self.index.sync_all()
+    }
+
+    /// Load blocks whose records are intact, and cut the rest if repair.
+    fn load(&mut self, repair: bool) -> io::Result<()> {
+        let mut log = Vec::new();
+        self.log.seek(SeekFrom::Start(0))?;
+        self.log.read_to_end(&mut log)?;
+        let mut index = Vec::new();
+        self.index.seek(SeekFrom::Start(0))?;
+        self.index.read_to_end(&mut index)?;
+
+        let mut end = 0;
+        for entry in index.chunks(INDEX_ENTRY_SIZE) {
+            if entry.len() < INDEX_ENTRY_SIZE {
+                break;
+            }
+            let mut offset_bytes = [0u8; INDEX_ENTRY_SIZE];
+            offset_bytes.copy_from_slice(entry);
+            let offset = u64::from_le_bytes(offset_bytes);
+            // Records are contiguous, any gap means a corrupted index.
+            if offset != end as u64 {
+                break;
+            }
+            match read_record(&log, end) {
+                Some((block, record_end)) => {
+                    self.blocks.push(block);
+                    self.offsets.push(offset);
+                    end = record_end;
+                }
+                None => break,
+            }
+        }
+        self.log_len = end as u64;
+
+        if self.log_len != log.len() as u64
+            || (self.offsets.len() * INDEX_ENTRY_SIZE) != index.len()
+        {
+            if !repair {
+                warn!(
+                    "block store is damaged or being written, load the first {} blocks.",
+                    self.blocks.len()
+                );
+                return Ok(());
+            }
+            warn!(
+                "block store is damaged, recover it with the first {} blocks.",
+                self.blocks.len()
+            );
+            self.cut()?;
+        }
+        Ok(())
+    }
+}
+
+impl BlockStore for FileStore {
+    fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    fn append(&mut self, block: Block) -> io::Result<()> {
+        let content = serde_json::to_vec(&block)?;
+        let mut record = Vec::with_capacity(RECORD_HEADER_SIZE + content.len());
+        record.extend_from_slice(&(content.len() as u32).to_le_bytes());
+        record.extend_from_slice(&checksum(&content));
+        record.extend_from_slice(&content);
+
+        let offset = self.log_len;
+        self.log.seek(SeekFrom::Start(offset))?;
+        self.log.write_all(&record)?;
+        self.log.sync_data()?;
+        self.index.seek(SeekFrom::Start(
+            (self.offsets.len() * INDEX_ENTRY_SIZE) as u64,
+        ))?;
+        self.index.write_all(&offset.to_le_bytes())?;
+        self.index.sync_data()?;
+
+        self.blocks.push(block);
+        self.offsets.push(offset);
+        self.log_len = offset + record.len() as u64;
+        Ok(())
+    }
+
+    fn truncate(&mut self, len: usize) -> io::Result<()> {
+        if len >= self.offsets.len() {
+            return Ok(());
+        }
+        self.log_len = self.offsets[len];
+        self.blocks.truncate(len);
+        self.offsets.truncate(len);
+        self.cut()
+    }
+}
+
+#[cfg(test)]
+mod test_file {
+    use super::*;
+    use blockchain::blockchain::BlockChain;
+    use blockchain::genesis::GenesisConfig;
+    use blockchain::ledger::LedgerMode;
+    use chrono::Utc;
+    use std::env;
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        env::temp_dir().join(format!(
+            "bam-chain-{}-{}",
+            name,
+            Utc::now().timestamp_nanos()
+        ))
+    }
+
+    fn blocks(count: usize) -> Vec<Block> {
+        let mut block_chain = BlockChain::new(&GenesisConfig::default(), LedgerMode::Account);
+        (0..count)
+            .map(|_| {
+                let block = block_chain.generate_next_block(Vec::new());
+                assert!(block_chain.add_new_block(block.clone()));
+                block
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_append_truncate_and_load() {
+        let dir = temp_dir("store");
+        let blocks = blocks(3);
+        {
+            let mut store = FileStore::open(&dir).unwrap();
+            assert!(store.blocks().is_empty());
+            for block in blocks.iter() {
+                store.append(block.clone()).unwrap();
+            }
+            store.truncate(2).unwrap();
+            store.append(blocks[2].clone()).unwrap();
+        }
+        let store = FileStore::open(&dir).unwrap();
+        assert_eq!(3, store.blocks().len());
+        assert_eq!(blocks[2].hash, store.blocks()[2].hash);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_recover_torn_writes() {
+        let dir = temp_dir("torn");
+        let blocks = blocks(2);
+        {
+            let mut store = FileStore::open(&dir).unwrap();
+            for block in blocks.iter() {
+                store.append(block.clone()).unwrap();
+            }
+        }
+        // Corrupt the last record, and leave a partial index entry.
+        let log_len = fs::metadata(dir.join(LOG_FILE)).unwrap().len();
+        let log = OpenOptions::new()
+            .write(true)
+            .open(dir.join(LOG_FILE))
+            .unwrap();
+        log.set_len(log_len - 1).unwrap();
+        let mut index = OpenOptions::new()
+            .append(true)
+            .open(dir.join(INDEX_FILE))
+            .unwrap();
+        index.write_all(&[0, 1]).unwrap();
+
+        // Read only opening leaves the damaged tail as it is.
+        assert_eq!(1, FileStore::open_read_only(&dir).unwrap().blocks().len());
+        assert_eq!(log_len - 1, fs::metadata(dir.join(LOG_FILE)).unwrap().len());
+
+        let mut store = FileStore::open(&dir).unwrap();
+        assert_eq!(1, store.blocks().len());
+        // The store keeps working after recovery.
+        store.append(blocks[1].clone()).unwrap();
+        assert_eq!(2, FileStore::open(&dir).unwrap().blocks().len());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
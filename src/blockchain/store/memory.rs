@@ -0,0 +1,32 @@
+//! The memory module keeps blocks in a vector, they're lost on restart.
+
+use super::BlockStore;
+use blockchain::blockchain::Block;
+use std::io;
+
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    blocks: Vec<Block>,
+}
+
+impl From<Vec<Block>> for MemoryStore {
+    fn from(blocks: Vec<Block>) -> Self {
+        MemoryStore { blocks }
+    }
+}
+
+impl BlockStore for MemoryStore {
+    fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    fn append(&mut self, block: Block) -> io::Result<()> {
+        self.blocks.push(block);
+        Ok(())
+    }
+
+    fn truncate(&mut self, len: usize) -> io::Result<()> {
+        self.blocks.truncate(len);
+        Ok(())
+    }
+}
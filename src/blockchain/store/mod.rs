@@ -0,0 +1,45 @@
+//! The store module holds the blocks of a chain.
+//! Two backends are supported, selected by the node config:
+//! the in-memory store, and the file store persisting blocks across restarts.
+
+pub mod file;
+pub mod memory;
+
+use self::file::FileStore;
+use self::memory::MemoryStore;
+use super::blockchain::Block;
+use std::fmt::Debug;
+use std::io;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreMode {
+    Memory,
+    File,
+}
+
+impl Default for StoreMode {
+    fn default() -> Self {
+        StoreMode::Memory
+    }
+}
+
+/// The blocks of a chain in ascending order, the chain only grows at or shrinks from the tip.
+pub trait BlockStore: Debug + Send + Sync {
+    fn blocks(&self) -> &[Block];
+
+    /// Append a block after stored ones.
+    fn append(&mut self, block: Block) -> io::Result<()>;
+
+    /// Keep only the first len blocks, e.g. when rolling back blocks on reorg.
+    fn truncate(&mut self, len: usize) -> io::Result<()>;
+}
+
+/// Create a store of the mode, the file store loads blocks persisted in the directory.
+pub fn new_store<P: AsRef<Path>>(mode: StoreMode, dir: P) -> io::Result<Box<BlockStore>> {
+    Ok(match mode {
+        StoreMode::Memory => Box::new(MemoryStore::default()),
+        StoreMode::File => Box::new(FileStore::open(dir)?),
+    })
+}
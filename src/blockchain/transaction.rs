@@ -0,0 +1,262 @@
+//! The transaction module contains the signed value transfers carried by blocks.
+
+use super::merkle::merkle_root;
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signature, Signer, Verifier};
+use hex;
+use serde_json;
+use sha3::{Digest, Sha3_256};
+use std::mem::transmute;
+
+/// Sender of coinbase transactions, which mint the block reward.
+pub const COINBASE_SENDER: &str = "";
+
+/// Coins minted in each block, besides the fees.
+pub const BLOCK_REWARD: u64 = 50;
+
+/// Maximum bytes of serialized transactions in a block, besides the coinbase.
+pub const MAX_BLOCK_SIZE: usize = 64 * 1024;
+
+/// Reference to an output of a previous transaction.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct OutPoint {
+    /// Hex encoded hash of the transaction creating the output.
+    pub tx_hash: String,
+    pub index: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transaction {
+    /// Hex encoded ed25519 public key of the sender, which is also the sender address.
+    pub sender: String,
+    /// Hex encoded address of the recipient.
+    pub recipient: String,
+    pub amount: u64,
+    pub fee: u64,
+    /// Per sender counter, prevents a transaction from being replayed.
+    /// Coinbase transactions use the block index instead.
+    pub nonce: u64,
+    /// Outputs spent by this transaction, only used in utxo ledger mode.
+    #[serde(default)]
+    pub inputs: Vec<OutPoint>,
+    pub signature: Vec<u8>,
+}
+
+impl Transaction {
+    /// Create a transaction signed by the sender keypair.
+    pub fn new_signed<S: Into<String>>(
+        keypair: &Keypair,
+        recipient: S,
+        amount: u64,
+        fee: u64,
+        nonce: u64,
+    ) -> Self {
+        Transaction::sign(keypair, recipient.into(), amount, fee, nonce, Vec::new())
+    }
+
+    /// Create a transaction spending the outputs, signed by the owner keypair.
+    pub fn new_signed_from_inputs<S: Into<String>>(
+        keypair: &Keypair,
+        inputs: Vec<OutPoint>,
+        recipient: S,
+        amount: u64,
+        fee: u64,
+    ) -> Self {
+        Transaction::sign(keypair, recipient.into(), amount, fee, 0, inputs)
+    }
+
+    fn sign(
+        keypair: &Keypair,
+        recipient: String,
+        amount: u64,
+        fee: u64,
+        nonce: u64,
+        inputs: Vec<OutPoint>,
+    ) -> Self {
+        let mut tx = Transaction {
+            sender: hex::encode(keypair.public.as_bytes()),
+            recipient,
+            amount,
+            fee,
+            nonce,
+            inputs,
+            signature: Vec::new(),
+        };
+        tx.signature = keypair.sign(&tx.signing_bytes()).to_bytes().to_vec();
+        tx
+    }
+
+    /// Create the coinbase transaction of the block at index, paying the reward to recipient.
+    pub fn coinbase<S: Into<String>>(recipient: S, amount: u64, index: u32) -> Self {
+        Transaction {
+            sender: COINBASE_SENDER.to_string(),
+            recipient: recipient.into(),
+            amount,
+            fee: 0,
+            nonce: index as u64,
+            inputs: Vec::new(),
+            signature: Vec::new(),
+        }
+    }
+
+    pub fn is_coinbase(&self) -> bool {
+        self.sender == COINBASE_SENDER
+    }
+
+    /// Bytes of the serialized transaction, counted against the block size limit.
+    pub fn size(&self) -> usize {
+        serde_json::to_vec(self).unwrap().len()
+    }
+
+    /// The content covered by the signature, i.e. all fields except the signature itself.
+    fn signing_bytes(&self) -> Vec<u8> {
+        let amount_byte: [u8; 8] = unsafe { transmute(self.amount.to_le()) };
+        let fee_byte: [u8; 8] = unsafe { transmute(self.fee.to_le()) };
+        let nonce_byte: [u8; 8] = unsafe { transmute(self.nonce.to_le()) };
+        let mut bytes = Vec::new();
+        bytes.extend(self.sender.as_bytes());
+        bytes.extend(self.recipient.as_bytes());
+        bytes.extend(&amount_byte);
+        bytes.extend(&fee_byte);
+        bytes.extend(&nonce_byte);
+        for input in &self.inputs {
+            let index_byte: [u8; 4] = unsafe { transmute(input.index.to_le()) };
+            bytes.extend(input.tx_hash.as_bytes());
+            bytes.extend(&index_byte);
+        }
+        bytes
+    }
+
+    /// The transaction hash, identifies a transaction.
+    pub fn hash(&self) -> Vec<u8> {
+        let mut hasher = Sha3_256::default();
+        hasher.input(&self.signing_bytes());
+        hasher.input(&self.signature);
+        hasher.result().as_slice().to_vec()
+    }
+
+    /// Verify the signature against the sender public key.
+    pub fn verify(&self) -> bool {
+        let public_key = match hex::decode(&self.sender) {
+            Ok(bytes) => PublicKey::from_bytes(&bytes),
+            Err(_) => return false,
+        };
+        let signature = Signature::from_bytes(&self.signature);
+        match (public_key, signature) {
+            (Ok(pk), Ok(sig)) => pk.verify(&self.signing_bytes(), &sig).is_ok(),
+            _ => false,
+        }
+    }
+}
+
+/// Validate transactions of a block regardless of the ledger state.
+/// All transactions should be properly signed, except the coinbase which can only be the first
+/// one, without inputs, and claims at most the block reward plus fees.
+/// The coinbase nonce should be the block index, so coinbases of different blocks never collide.
+/// Transactions besides the coinbase should fit in the block size limit.
+pub fn validate_block_transactions(transactions: &[Transaction], index: u32) -> bool {
+    let size: usize = transactions
+        .iter()
+        .filter(|tx| !tx.is_coinbase())
+        .map(Transaction::size)
+        .sum();
+    if size > MAX_BLOCK_SIZE {
+        debug!("validation failed: block exceeds the size limit.");
+        return false;
+    }
+    let fees = transactions
+        .iter()
+        .filter(|tx| !tx.is_coinbase())
+        .fold(Some(BLOCK_REWARD), |sum, tx| {
+            sum.and_then(|s| s.checked_add(tx.fee))
+        });
+    for (i, tx) in transactions.iter().enumerate() {
+        if tx.is_coinbase() {
+            if i != 0 || !tx.inputs.is_empty() {
+                debug!("validation failed: misplaced coinbase transaction.");
+                return false;
+            }
+            if tx.nonce != u64::from(index) {
+                debug!("validation failed: coinbase nonce is not the block index.");
+                return false;
+            }
+            if fees.map_or(true, |max_reward| tx.amount > max_reward) {
+                debug!("validation failed: coinbase claims more than block reward and fees.");
+                return false;
+            }
+        } else if !tx.verify() {
+            debug!("validation failed: invalid transaction signature.");
+            return false;
+        }
+    }
+    true
+}
+
+/// Restore the keypair from the hex encoded secret key.
+pub fn keypair_from_secret(secret: &str) -> Result<Keypair, String> {
+    let secret = hex::decode(secret)
+        .map_err(|_| "invalid secret key.".to_string())
+        .and_then(|bytes| SecretKey::from_bytes(&bytes).map_err(|err| err.to_string()))?;
+    let public = PublicKey::from(&secret);
+    Ok(Keypair { secret, public })
+}
+
+/// Merkle root of all transactions in a block, committed by the block hash.
+pub fn transactions_root(transactions: &[Transaction]) -> Vec<u8> {
+    merkle_root(&transaction_leaves(transactions))
+}
+
+/// Merkle leaves of transactions, i.e. the transaction hashes.
+pub fn transaction_leaves(transactions: &[Transaction]) -> Vec<Vec<u8>> {
+    transactions.iter().map(|tx| tx.hash()).collect()
+}
+
+#[cfg(test)]
+mod test_transaction {
+    use super::*;
+    use rand::rngs::OsRng;
+
+    #[test]
+    fn test_verify_signature() {
+        let keypair = Keypair::generate(&mut OsRng);
+        let tx = Transaction::new_signed(&keypair, "recipient", 10, 1, 0);
+        assert!(tx.verify());
+
+        let mut tampered = tx.clone();
+        tampered.amount = 100;
+        assert!(!tampered.verify());
+
+        let mut forged = tx.clone();
+        forged.sender = hex::encode(Keypair::generate(&mut OsRng).public.as_bytes());
+        assert!(!forged.verify());
+
+        let restored = keypair_from_secret(&hex::encode(keypair.secret.as_bytes())).unwrap();
+        assert_eq!(keypair.public, restored.public);
+        assert!(keypair_from_secret("00ab").is_err());
+    }
+
+    #[test]
+    fn test_validate_coinbase() {
+        let keypair = Keypair::generate(&mut OsRng);
+        let tx = Transaction::new_signed(&keypair, "recipient", 10, 1, 0);
+        let reward = Transaction::coinbase("miner", BLOCK_REWARD + 1, 1);
+        assert!(validate_block_transactions(
+            &[reward.clone(), tx.clone()],
+            1
+        ));
+        assert!(!validate_block_transactions(
+            &[tx.clone(), reward.clone()],
+            1
+        ));
+        // The same coinbase in another block.
+        assert!(!validate_block_transactions(
+            &[reward.clone(), tx.clone()],
+            2
+        ));
+
+        let greedy = Transaction::coinbase("miner", BLOCK_REWARD + 2, 1);
+        assert!(!validate_block_transactions(&[greedy, tx.clone()], 1));
+
+        let oversize = vec![tx; MAX_BLOCK_SIZE / 100];
+        assert!(!validate_block_transactions(&oversize, 1));
+    }
+}
@@ -0,0 +1,258 @@
+//! The cli module parses command line arguments.
+//!
+//! ```text
+//! bam-chain [PORT] [CONFIG]                     run a node
+//! bam-chain export --out FILE [--config CONFIG] export own chain to file
+//! bam-chain import --in FILE [--config CONFIG]  import a chain from file
+//! bam-chain keygen                              generate a keypair
+//! bam-chain sign --key SECRET --to ADDRESS --amount N [--fee N] [--nonce N] [--input TX:INDEX]...
+//!                                               sign a transaction
+//! ```
+
+use blockchain::transaction::OutPoint;
+use std::env;
+use std::process;
+
+pub const DEFAULT_PORT: u32 = 8191;
+
+pub const USAGE: &str = "usage:
+    bam-chain [PORT] [CONFIG]
+    bam-chain export --out FILE [--config CONFIG]
+    bam-chain import --in FILE [--config CONFIG]
+    bam-chain keygen
+    bam-chain sign --key SECRET --to ADDRESS --amount N [--fee N] [--nonce N] [--input TX:INDEX]...";
+
+lazy_static! {
+    /// The parsed arguments, exit with the usage if they're invalid.
+    pub static ref ARGS: Args = Args::parse(env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{}\n{}", err, USAGE);
+        process::exit(2);
+    });
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Run a node listening on the port.
+    Serve,
+    /// Export own chain to the file.
+    Export(String),
+    /// Import the chain in the file.
+    Import(String),
+    /// Generate a keypair, whose public key is an address.
+    Keygen,
+    /// Sign a transaction.
+    Sign(SignOptions),
+}
+
+/// Fields of the transaction to sign, the secret key is hex encoded.
+/// The transaction spends the inputs in utxo ledger mode, or uses the nonce otherwise.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SignOptions {
+    pub key: String,
+    pub to: String,
+    pub amount: u64,
+    pub fee: u64,
+    pub nonce: u64,
+    pub inputs: Vec<OutPoint>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Args {
+    pub command: Command,
+    pub port: u32,
+    /// Path of the node config file.
+    pub config: Option<String>,
+}
+
+impl Args {
+    pub fn parse<I: Iterator<Item = String>>(args: I) -> Result<Self, String> {
+        let args: Vec<String> = args.collect();
+        match args.first().map(|arg| arg.as_str()) {
+            Some("export") => {
+                Args::parse_snapshot(&args[1..], "--out").map(|(path, config)| Args {
+                    command: Command::Export(path),
+                    port: DEFAULT_PORT,
+                    config,
+                })
+            }
+            Some("import") => Args::parse_snapshot(&args[1..], "--in").map(|(path, config)| Args {
+                command: Command::Import(path),
+                port: DEFAULT_PORT,
+                config,
+            }),
+            Some("keygen") => Ok(Args {
+                command: Command::Keygen,
+                port: DEFAULT_PORT,
+                config: None,
+            }),
+            Some("sign") => Args::parse_sign(&args[1..]).map(|options| Args {
+                command: Command::Sign(options),
+                port: DEFAULT_PORT,
+                config: None,
+            }),
+            _ => Ok(Args {
+                command: Command::Serve,
+                port: args
+                    .get(0)
+                    .and_then(|port| port.parse().ok())
+                    .unwrap_or(DEFAULT_PORT),
+                config: args.get(1).cloned(),
+            }),
+        }
+    }
+
+    /// Parse the options of export and import, return the file path and the config path.
+    fn parse_snapshot(
+        args: &[String],
+        path_flag: &str,
+    ) -> Result<(String, Option<String>), String> {
+        let mut path = None;
+        let mut config = None;
+        let mut args = args.iter();
+        while let Some(flag) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("missing value of {}.", flag))?;
+            if flag == path_flag {
+                path = Some(value.clone());
+            } else if flag == "--config" {
+                config = Some(value.clone());
+            } else {
+                return Err(format!("unknown option {}.", flag));
+            }
+        }
+        path.map(|path| (path, config))
+            .ok_or_else(|| format!("missing {} FILE.", path_flag))
+    }
+
+    /// Parse the options of sign, the fee and nonce are zero if absent.
+    /// Inputs are given as the hex encoded transaction hash and the output index, e.g. `00ab:1`.
+    fn parse_sign(args: &[String]) -> Result<SignOptions, String> {
+        let mut key = None;
+        let mut to = None;
+        let mut amount = None;
+        let mut fee = 0;
+        let mut nonce = 0;
+        let mut inputs = Vec::new();
+        let mut args = args.iter();
+        while let Some(flag) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("missing value of {}.", flag))?;
+            let number = || {
+                value
+                    .parse::<u64>()
+                    .map_err(|_| format!("invalid value of {}.", flag))
+            };
+            match flag.as_str() {
+                "--key" => key = Some(value.clone()),
+                "--to" => to = Some(value.clone()),
+                "--amount" => amount = Some(number()?),
+                "--fee" => fee = number()?,
+                "--nonce" => nonce = number()?,
+                "--input" => inputs.push(Args::parse_out_point(value)?),
+                _ => return Err(format!("unknown option {}.", flag)),
+            }
+        }
+        Ok(SignOptions {
+            key: key.ok_or("missing --key SECRET.")?,
+            to: to.ok_or("missing --to ADDRESS.")?,
+            amount: amount.ok_or("missing --amount N.")?,
+            fee,
+            nonce,
+            inputs,
+        })
+    }
+
+    fn parse_out_point(value: &str) -> Result<OutPoint, String> {
+        let mut parts = value.splitn(2, ':');
+        match (
+            parts.next(),
+            parts.next().and_then(|index| index.parse().ok()),
+        ) {
+            (Some(tx_hash), Some(index)) if !tx_hash.is_empty() => Ok(OutPoint {
+                tx_hash: tx_hash.to_string(),
+                index,
+            }),
+            _ => Err(format!("invalid input {}.", value)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_cli {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        Args::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse_serve() {
+        assert_eq!(
+            Args {
+                command: Command::Serve,
+                port: 8282,
+                config: Some("node.json".to_string()),
+            },
+            parse(&["8282", "node.json"]).unwrap()
+        );
+        assert_eq!(DEFAULT_PORT, parse(&[]).unwrap().port);
+    }
+
+    #[test]
+    fn test_parse_snapshot() {
+        assert_eq!(
+            Args {
+                command: Command::Export("chain.json".to_string()),
+                port: DEFAULT_PORT,
+                config: Some("node.json".to_string()),
+            },
+            parse(&["export", "--out", "chain.json", "--config", "node.json"]).unwrap()
+        );
+        assert_eq!(
+            Command::Import("chain.json".to_string()),
+            parse(&["import", "--in", "chain.json"]).unwrap().command
+        );
+        assert!(parse(&["import", "--out", "chain.json"]).is_err());
+        assert!(parse(&["export"]).is_err());
+        assert!(parse(&["export", "--out"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_sign() {
+        assert_eq!(
+            Command::Sign(SignOptions {
+                key: "00ab".to_string(),
+                to: "bob".to_string(),
+                amount: 30,
+                fee: 1,
+                nonce: 0,
+                inputs: Vec::new(),
+            }),
+            parse(&["sign", "--key", "00ab", "--to", "bob", "--amount", "30", "--fee", "1"])
+                .unwrap()
+                .command
+        );
+        assert!(parse(&["sign", "--key", "00ab", "--to", "bob"]).is_err());
+        assert!(parse(&["sign", "--key", "00ab", "--to", "bob", "--amount", "x"]).is_err());
+        let args = [
+            "sign", "--key", "00ab", "--to", "bob", "--amount", "30", "--input", "cd:1",
+        ];
+        match parse(&args).unwrap().command {
+            Command::Sign(options) => assert_eq!(
+                vec![OutPoint {
+                    tx_hash: "cd".to_string(),
+                    index: 1,
+                }],
+                options.inputs
+            ),
+            command => panic!("unexpected command: {:?}", command),
+        }
+        assert!(parse(&[
+            "sign", "--key", "00ab", "--to", "bob", "--amount", "30", "--input", "cd"
+        ])
+        .is_err());
+        assert_eq!(Command::Keygen, parse(&["keygen"]).unwrap().command);
+    }
+}
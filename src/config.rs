@@ -0,0 +1,124 @@
+//! The config module loads the node configuration from a json file.
+
+use blockchain::blockchain::BlockChain;
+use blockchain::genesis::GenesisConfig;
+use blockchain::ledger::LedgerMode;
+use blockchain::store::file::FileStore;
+use blockchain::store::{new_store, BlockStore, StoreMode};
+use cli::ARGS;
+use peers::store::{PeerStore, DEFAULT_MAX_STORED_PEERS, DEFAULT_STALE_PEER_AGE};
+use serde_json;
+use std::fs::File;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+
+/// Directory of the file store, if not configured.
+pub const DEFAULT_DATA_DIR: &str = "data";
+
+lazy_static! {
+    /// The node config, loaded from the file specified by the arguments.
+    pub static ref NODE_CONFIG: NodeConfig = match ARGS.config {
+        Some(ref path) => NodeConfig::from_file(path).expect("failed to load the node config."),
+        None => NodeConfig::default(),
+    };
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct NodeConfig {
+    /// Path of the genesis config file, use the default genesis block if not set.
+    pub genesis: Option<String>,
+    /// The ledger model, either "account" or "utxo".
+    pub ledger: LedgerMode,
+    /// Address receiving the block reward of mined blocks, no reward is claimed if not set.
+    pub miner_address: Option<String>,
+    /// Seconds between pulling the latest blocks of peers, use the default interval if not set.
+    pub pull_sync_interval: Option<u64>,
+    /// Seconds between probing the liveness of peers, use the default interval if not set.
+    pub peer_probe_interval: Option<u64>,
+    /// Failed probes in a row before a peer is removed, use the default limit if not set.
+    /// Peers are never removed for failures if it's zero.
+    pub max_peer_failures: Option<u32>,
+    /// Addresses of seed nodes, added as peers on startup to discover the network.
+    pub seeds: Vec<String>,
+    /// Seconds between exchanging peers with neighbors, use the default interval if not set.
+    pub discovery_interval: Option<u64>,
+    /// Seconds a misbehaving peer is banned for, use the default duration if not set.
+    pub ban_duration: Option<i64>,
+    /// The block store, either "memory" or "file". Peers are stored as well with the file store.
+    pub store: StoreMode,
+    /// Directory of the file store.
+    pub data_dir: String,
+    /// Maximum number of stored peers, use the default limit if not set.
+    pub max_stored_peers: Option<usize>,
+    /// Seconds since a peer is last seen before it's evicted, use the default age if not set.
+    pub stale_peer_age: Option<i64>,
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        NodeConfig {
+            genesis: None,
+            ledger: LedgerMode::default(),
+            miner_address: None,
+            pull_sync_interval: None,
+            peer_probe_interval: None,
+            max_peer_failures: None,
+            seeds: Vec::new(),
+            discovery_interval: None,
+            ban_duration: None,
+            store: StoreMode::default(),
+            data_dir: String::from(DEFAULT_DATA_DIR),
+            max_stored_peers: None,
+            stale_peer_age: None,
+        }
+    }
+}
+
+impl NodeConfig {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        serde_json::from_reader(file).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+    }
+
+    /// Load the genesis config specified by this node config.
+    pub fn load_genesis(&self) -> GenesisConfig {
+        match self.genesis {
+            Some(ref path) => {
+                GenesisConfig::from_file(path).expect("failed to load the genesis config.")
+            }
+            None => GenesisConfig::default(),
+        }
+    }
+
+    /// Open the block chain held by the configured store.
+    pub fn open_block_chain(&self) -> io::Result<BlockChain> {
+        let store = new_store(self.store, &self.data_dir)?;
+        BlockChain::open(&self.load_genesis(), self.ledger, store)
+    }
+
+    /// Open the chain persisted in the data directory without modifying the store,
+    /// so it's safe while a node is running on it.
+    pub fn open_stored_block_chain(&self) -> io::Result<BlockChain> {
+        let store = FileStore::open_read_only(&self.data_dir)?;
+        if store.blocks().is_empty() {
+            return Err(io::Error::new(
+                ErrorKind::NotFound,
+                "no blocks stored in the data directory.",
+            ));
+        }
+        BlockChain::open(&self.load_genesis(), self.ledger, Box::new(store))
+    }
+
+    /// Open the peer store in the data directory, peers are kept in memory only with the memory store.
+    pub fn open_peer_store(&self) -> Option<PeerStore> {
+        match self.store {
+            StoreMode::Memory => None,
+            StoreMode::File => Some(PeerStore::new(
+                &self.data_dir,
+                self.max_stored_peers.unwrap_or(DEFAULT_MAX_STORED_PEERS),
+                self.stale_peer_age.unwrap_or(DEFAULT_STALE_PEER_AGE),
+            )),
+        }
+    }
+}
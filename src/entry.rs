@@ -1,19 +1,27 @@
 //! The servicc module serve the external communication for all nodes.
 
 use blockchain::BlockChainAPIs;
+use config::NODE_CONFIG;
+use extractors::{
+    AddressPath, BlocksQuery, HashPath, HeadersQuery, IndexPath, ListPeersQuery, ProofPath,
+};
 use gotham::router::{Router, builder::*};
 use gotham::state::State;
 use gotham::{self, http::response::create_response};
 use hyper::{Response, StatusCode};
 use mime::TEXT_PLAIN;
+use peers::handshake::LocalNode;
 use peers::PeerAPIs;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 
 pub fn start(port: u32) {
     let addr = format!("0.0.0.0:{}", port);
     info!("spawn a miner server at {}", addr);
     let entry_service = EntryService::new();
     let shared_entry_service = Arc::new(entry_service);
+    shared_entry_service.block_chain_apis.spawn_pull_sync();
+    shared_entry_service.peer_apis.spawn_prober();
+    shared_entry_service.peer_apis.spawn_discovery();
     gotham::start(addr, EntryService::router(shared_entry_service.clone()));
 }
 
@@ -26,9 +34,15 @@ pub struct EntryService {
 
 impl EntryService {
     fn new() -> Self {
-        let peer_apis = Arc::new(PeerAPIs::new());
+        let block_chain = Arc::new(RwLock::new(
+            NODE_CONFIG
+                .open_block_chain()
+                .expect("failed to load the block store."),
+        ));
+        let local = LocalNode::new(block_chain.clone(), NODE_CONFIG.load_genesis().network_id);
+        let peer_apis = Arc::new(PeerAPIs::new(local));
         EntryService {
-            block_chain_apis: BlockChainAPIs::new(peer_apis.clone()),
+            block_chain_apis: BlockChainAPIs::new(block_chain, peer_apis.clone()),
             peer_apis,
         }
     }
@@ -46,6 +60,17 @@ impl EntryService {
         (state, res)
     }
 
+    /// Serve the request of peers, banned ones are responded Forbidden.
+    fn serve_peer<F>(&self, state: State, handler: F) -> (State, Response)
+    where
+        F: FnOnce(State) -> (State, Response),
+    {
+        match self.peer_apis.reject_banned(&state, None) {
+            Some(res) => (state, res),
+            None => handler(state),
+        }
+    }
+
     /// Router generator static method.
     fn router(entry_service: Arc<EntryService>) -> Router {
         build_simple_router(|route| {
@@ -67,12 +92,142 @@ impl EntryService {
                 })
             });
 
+            // Listing a page of blocks in this node.
+            let shared_entry_service = entry_service.clone();
+            route
+                .get("/blocks")
+                .with_query_string_extractor::<BlocksQuery>()
+                .to_new_handler(move || {
+                    Ok(|state| shared_entry_service.block_chain_apis.list_blocks(state))
+                });
+
+            // Listing recently applied reorgs.
+            let shared_entry_service = entry_service.clone();
+            route.get("/reorgs").to_new_handler(move || {
+                Ok(|state| shared_entry_service.block_chain_apis.list_reorgs(state))
+            });
+
+            // Listing block headers in this node.
+            let shared_entry_service = entry_service.clone();
+            route
+                .get("/headers")
+                .with_query_string_extractor::<HeadersQuery>()
+                .to_new_handler(move || {
+                    Ok(|state| {
+                        shared_entry_service.serve_peer(state, |state| {
+                            shared_entry_service.block_chain_apis.get_headers(state)
+                        })
+                    })
+                });
+
+            // Get the header of a block.
+            let shared_entry_service = entry_service.clone();
+            route
+                .get("/header/:index")
+                .with_path_extractor::<IndexPath>()
+                .to_new_handler(move || {
+                    Ok(|state| shared_entry_service.block_chain_apis.get_header(state))
+                });
+
+            // Get the body of a block.
+            let shared_entry_service = entry_service.clone();
+            route
+                .get("/body/:index")
+                .with_path_extractor::<IndexPath>()
+                .to_new_handler(move || {
+                    Ok(|state| {
+                        shared_entry_service.serve_peer(state, |state| {
+                            shared_entry_service.block_chain_apis.get_body(state)
+                        })
+                    })
+                });
+
+            // Get a block by its hex encoded hash.
+            let shared_entry_service = entry_service.clone();
+            route
+                .get("/block/:hash")
+                .with_path_extractor::<HashPath>()
+                .to_new_handler(move || {
+                    Ok(|state| {
+                        shared_entry_service
+                            .block_chain_apis
+                            .get_block_by_hash(state)
+                    })
+                });
+
+            // Get a block by its height.
+            let shared_entry_service = entry_service.clone();
+            route
+                .get("/block/height/:index")
+                .with_path_extractor::<IndexPath>()
+                .to_new_handler(move || {
+                    Ok(|state| {
+                        shared_entry_service
+                            .block_chain_apis
+                            .get_block_by_height(state)
+                    })
+                });
+
             // Mine a block.
             let shared_entry_service = entry_service.clone();
             route.post("/mine").to_new_handler(move || {
                 Ok(|state| shared_entry_service.block_chain_apis.mine_block(state))
             });
 
+            // Submit a signed transaction into mempool.
+            let shared_entry_service = entry_service.clone();
+            route.post("/tx").to_new_handler(move || {
+                Ok(|state| {
+                    shared_entry_service
+                        .block_chain_apis
+                        .submit_transaction(state)
+                })
+            });
+
+            // Get the balance of an address.
+            let shared_entry_service = entry_service.clone();
+            route
+                .get("/balance/:address")
+                .with_path_extractor::<AddressPath>()
+                .to_new_handler(move || {
+                    Ok(|state| shared_entry_service.block_chain_apis.get_balance(state))
+                });
+
+            // Get the balance and nonce of an address.
+            let shared_entry_service = entry_service.clone();
+            route
+                .get("/account/:address")
+                .with_path_extractor::<AddressPath>()
+                .to_new_handler(move || {
+                    Ok(|state| shared_entry_service.block_chain_apis.get_account(state))
+                });
+
+            // Get the unspent outputs of an address.
+            let shared_entry_service = entry_service.clone();
+            route
+                .get("/utxos/:address")
+                .with_path_extractor::<AddressPath>()
+                .to_new_handler(move || {
+                    Ok(|state| {
+                        shared_entry_service
+                            .block_chain_apis
+                            .get_unspent_outputs(state)
+                    })
+                });
+
+            // Get the merkle inclusion proof of a transaction in a block.
+            let shared_entry_service = entry_service.clone();
+            route
+                .get("/proof/:block/:tx")
+                .with_path_extractor::<ProofPath>()
+                .to_new_handler(move || {
+                    Ok(|state| {
+                        shared_entry_service
+                            .block_chain_apis
+                            .get_transaction_proof(state)
+                    })
+                });
+
             // Add peers from carriers.
             let shared_entry_service = entry_service.clone();
             route.post("/add_peers").to_new_handler(move || {
@@ -81,19 +236,43 @@ impl EntryService {
 
             // List peers of in this node.
             let shared_entry_service = entry_service.clone();
-            route.get("/list_peers").to_new_handler(move || {
-                Ok(|state| shared_entry_service.peer_apis.list_peers(state))
+            route
+                .get("/list_peers")
+                .with_query_string_extractor::<ListPeersQuery>()
+                .to_new_handler(move || {
+                    Ok(|state| shared_entry_service.peer_apis.list_peers(state))
+                });
+
+            // List banned peers.
+            let shared_entry_service = entry_service.clone();
+            route.get("/banned").to_new_handler(move || {
+                Ok(|state| shared_entry_service.peer_apis.list_banned(state))
             });
 
+            // Lift the ban of a peer.
+            let shared_entry_service = entry_service.clone();
+            route
+                .delete("/banned/:address")
+                .with_path_extractor::<AddressPath>()
+                .to_new_handler(move || Ok(|state| shared_entry_service.peer_apis.unban(state)));
+
             // ------------ Internal routes for miner nodes communications ------------
 
+            // Exchange handshakes, before a node is marked live.
+            let shared_entry_service = entry_service.clone();
+            route.post("/handshake").to_new_handler(move || {
+                Ok(|state| shared_entry_service.peer_apis.handshake(state))
+            });
+
             // Response latest block.
             let shared_entry_service = entry_service.clone();
             route.get("/response_latest_block").to_new_handler(move || {
                 Ok(|state| {
-                    shared_entry_service
-                        .block_chain_apis
-                        .response_latest_block(state)
+                    shared_entry_service.serve_peer(state, |state| {
+                        shared_entry_service
+                            .block_chain_apis
+                            .response_latest_block(state)
+                    })
                 })
             });
 
@@ -101,9 +280,11 @@ impl EntryService {
             let shared_entry_service = entry_service.clone();
             route.get("/response_whole_chain").to_new_handler(move || {
                 Ok(|state| {
-                    shared_entry_service
-                        .block_chain_apis
-                        .response_whole_chain(state)
+                    shared_entry_service.serve_peer(state, |state| {
+                        shared_entry_service
+                            .block_chain_apis
+                            .response_whole_chain(state)
+                    })
                 })
             });
 
@@ -116,6 +297,22 @@ impl EntryService {
                         .publish_block_handler(state)
                 })
             });
+
+            // Publish transactions to this node.
+            let shared_entry_service = entry_service.clone();
+            route.post("/publish_tx").to_new_handler(move || {
+                Ok(|state| {
+                    shared_entry_service
+                        .block_chain_apis
+                        .publish_transaction_handler(state)
+                })
+            });
+
+            // Catch up with peers by headers first sync.
+            let shared_entry_service = entry_service.clone();
+            route.post("/sync").to_new_handler(move || {
+                Ok(|state| shared_entry_service.block_chain_apis.sync_with_peers(state))
+            });
         })
     }
 }
@@ -0,0 +1,87 @@
+//! The extractors module defines path and query string parameters of routes.
+
+use blockchain::blockchain::BlockOrder;
+use gotham::router::response::extender::StaticResponseExtender;
+use gotham::state::{State, StateData};
+use hyper::{Response, StatusCode};
+
+/// Implement the traits required by gotham extractors,
+/// response bad request if the parameters can't be extracted.
+macro_rules! impl_extractor {
+    ($t:ty) => {
+        impl StateData for $t {}
+
+        impl StaticResponseExtender for $t {
+            fn extend(_state: &mut State, res: &mut Response) {
+                res.set_status(StatusCode::BadRequest);
+            }
+        }
+    };
+}
+
+/// Path parameters of address related routes, i.e. `/balance/:address` and `/banned/:address`.
+#[derive(Deserialize)]
+pub struct AddressPath {
+    pub address: String,
+}
+
+impl_extractor!(AddressPath);
+
+/// Path parameters of block related routes, i.e. `/header/:index` and `/body/:index`.
+#[derive(Deserialize)]
+pub struct IndexPath {
+    pub index: u32,
+}
+
+impl_extractor!(IndexPath);
+
+/// Path parameters of the block route, i.e. `/block/:hash`.
+/// The hash is the hex encoded block hash.
+#[derive(Deserialize)]
+pub struct HashPath {
+    pub hash: String,
+}
+
+impl_extractor!(HashPath);
+
+/// Query string parameters of the headers route, i.e. `/headers?from=N&count=M`.
+/// Headers are listed from the genesis block if from is absent.
+#[derive(Deserialize)]
+pub struct HeadersQuery {
+    pub from: Option<u32>,
+    pub count: Option<u32>,
+}
+
+impl_extractor!(HeadersQuery);
+
+/// Query string parameters of the blocks route, i.e. `/blocks?from=N&to=M&limit=L&order=desc`.
+/// The cursor is the next cursor responsed with the previous page.
+#[derive(Deserialize)]
+pub struct BlocksQuery {
+    pub from: Option<u32>,
+    pub to: Option<u32>,
+    pub limit: Option<u32>,
+    pub order: Option<BlockOrder>,
+    pub cursor: Option<u32>,
+}
+
+impl_extractor!(BlocksQuery);
+
+/// Query string parameters of the list peers route, i.e. `/list_peers?port=N`.
+/// Nodes exchanging peers tell their listening port, so they're reachable as peers.
+#[derive(Deserialize)]
+pub struct ListPeersQuery {
+    pub port: Option<u32>,
+}
+
+impl_extractor!(ListPeersQuery);
+
+/// Path parameters of the transaction proof route, i.e. `/proof/:block/:tx`.
+/// The block is the block index, and the tx is the hex encoded transaction hash.
+#[derive(Deserialize)]
+pub struct ProofPath {
+    pub block: u32,
+    pub tx: String,
+}
+
+impl_extractor!(ProofPath);
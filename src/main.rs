@@ -4,9 +4,11 @@
 #[macro_use]
 extern crate log;
 extern crate chrono;
+extern crate ed25519_dalek;
 extern crate env_logger;
 extern crate futures;
 extern crate gotham;
+extern crate hex;
 extern crate hyper;
 extern crate mime;
 extern crate sha3;
@@ -14,18 +16,101 @@ extern crate sha3;
 extern crate serde_derive;
 #[macro_use]
 extern crate lazy_static;
+extern crate rand;
 extern crate serde;
 extern crate serde_json;
 extern crate tokio_core;
 
 mod blockchain;
+mod cli;
+mod config;
 mod entry;
+mod extractors;
 mod peers;
 
+use blockchain::blockchain::BlockChain;
+use blockchain::fork_choice::ForkChoice;
+use blockchain::snapshot::{export_chain, import_chain};
+use blockchain::store::StoreMode;
+use blockchain::transaction::{keypair_from_secret, Transaction};
+use cli::{Command, ARGS};
+use config::NODE_CONFIG;
+use ed25519_dalek::Keypair;
 use peers::LISTENED_PORT;
+use rand::rngs::OsRng;
+use std::fmt::Display;
+use std::process;
 
 fn main() {
     env_logger::init();
-    info!("simple blockchain implementation in rust.");
-    entry::start(*LISTENED_PORT);
+    match ARGS.command {
+        Command::Serve => {
+            info!("simple blockchain implementation in rust.");
+            entry::start(*LISTENED_PORT);
+        }
+        Command::Export(ref path) => {
+            // Only the file store holds blocks of another node.
+            require_file_store("export");
+            let block_chain = exit_on_error(NODE_CONFIG.open_stored_block_chain());
+            exit_on_error(export_chain(&block_chain, path));
+            println!("exported {} blocks to {}.", block_chain.len(), path);
+        }
+        Command::Import(ref path) => {
+            // Blocks imported into memory are lost on exit.
+            require_file_store("import");
+            let mut block_chain = open_block_chain();
+            let applied =
+                exit_on_error(import_chain(&mut block_chain, path, &ForkChoice::default()));
+            println!("imported {} blocks from {}.", applied, path);
+        }
+        Command::Keygen => {
+            let keypair = Keypair::generate(&mut OsRng);
+            println!("secret: {}", hex::encode(keypair.secret.as_bytes()));
+            println!("address: {}", hex::encode(keypair.public.as_bytes()));
+        }
+        Command::Sign(ref options) => {
+            let keypair = exit_on_error(keypair_from_secret(&options.key));
+            let tx = if options.inputs.is_empty() {
+                Transaction::new_signed(
+                    &keypair,
+                    options.to.as_str(),
+                    options.amount,
+                    options.fee,
+                    options.nonce,
+                )
+            } else {
+                Transaction::new_signed_from_inputs(
+                    &keypair,
+                    options.inputs.clone(),
+                    options.to.as_str(),
+                    options.amount,
+                    options.fee,
+                )
+            };
+            println!("{}", serde_json::to_string(&tx).unwrap());
+        }
+    }
+}
+
+fn open_block_chain() -> BlockChain {
+    exit_on_error(NODE_CONFIG.open_block_chain())
+}
+
+/// Exit unless the node config selects the file store.
+fn require_file_store(command: &str) {
+    if NODE_CONFIG.store == StoreMode::Memory {
+        eprintln!(
+            "{} requires the file store, set store to \"file\" in the node config.",
+            command
+        );
+        process::exit(1);
+    }
+}
+
+/// Unwrap the result of a command, or exit with the error.
+fn exit_on_error<T, E: Display>(result: Result<T, E>) -> T {
+    result.unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    })
 }
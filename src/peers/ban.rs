@@ -0,0 +1,336 @@
+//! The ban module scores peers by their behavior, and bans misbehaving peers for a while.
+//!
+//! A peer starts with a zero score, which drops on every misbehavior and recovers over time.
+//! Once the score crosses the threshold, the peer is banned and its score is reset.
+//! Peers are scored and banned by host, so a peer can't escape its ban by claiming another port.
+//! Loopback and private hosts commonly run several nodes, so their peers are scored and banned
+//! by the whole address instead.
+
+use chrono::Utc;
+use std::cmp;
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+/// Peers whose score drops to the threshold are banned.
+pub const BAN_THRESHOLD: i32 = -100;
+
+/// Seconds a peer is banned for, if not configured.
+pub const DEFAULT_BAN_DURATION: i64 = 60 * 60;
+
+/// Seconds for a score to recover one point.
+pub const SCORE_RECOVERY_INTERVAL: i64 = 60;
+
+/// Maximum number of scored hosts, recovered and then least recently penalized ones are evicted.
+pub const MAX_SCORED_HOSTS: usize = 1000;
+
+/// The host of the peer address, or the address itself if it has no port.
+pub fn host_of(address: &str) -> &str {
+    if address.parse::<IpAddr>().is_ok() {
+        return address;
+    }
+    let host = address.rsplitn(2, ':').last().unwrap_or(address);
+    if host.starts_with('[') && host.ends_with(']') {
+        &host[1..host.len() - 1]
+    } else {
+        host
+    }
+}
+
+/// Check if the ip is a loopback, private or link local one.
+fn is_local(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_loopback() || ip.is_private() || ip.is_link_local(),
+        IpAddr::V6(ip) => {
+            let segment = ip.segments()[0];
+            ip.is_loopback() || segment & 0xfe00 == 0xfc00 || segment & 0xffc0 == 0xfe80
+        }
+    }
+}
+
+/// The key which the peer address is scored and banned by, i.e. the host of the address,
+/// or the whole address for local hosts. A local host without port has no key.
+pub fn ban_key(address: &str) -> Option<&str> {
+    let host = host_of(address);
+    match host.parse::<IpAddr>() {
+        Ok(ip) if is_local(ip) => {
+            if host == address {
+                None
+            } else {
+                Some(address)
+            }
+        }
+        _ => Some(host),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Misbehavior {
+    /// Blocks or headers failing the validation.
+    InvalidBlocks,
+    /// Messages which can't be parsed.
+    MalformedMessage,
+    /// Requests not responded in time.
+    Timeout,
+}
+
+impl Misbehavior {
+    fn penalty(self) -> i32 {
+        match self {
+            Misbehavior::InvalidBlocks => 50,
+            Misbehavior::MalformedMessage => 20,
+            Misbehavior::Timeout => 10,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Ban {
+    pub address: String,
+    /// Unix timestamp when the ban is lifted.
+    pub until: i64,
+}
+
+fn default_ban_duration() -> i64 {
+    DEFAULT_BAN_DURATION
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Score {
+    value: i32,
+    /// Unix timestamp of the last misbehavior, the score recovers since then.
+    penalized: i64,
+}
+
+impl Score {
+    /// The score recovered until now, never above zero.
+    fn at(&self, now: i64) -> i32 {
+        let recovered = cmp::max(0, now - self.penalized) / SCORE_RECOVERY_INTERVAL;
+        cmp::min(0, i64::from(self.value) + recovered) as i32
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BanList {
+    /// Scores of peers keyed by `ban_key`, absent peers have a zero score.
+    scores: HashMap<String, Score>,
+    /// Ends of bans keyed by `ban_key`.
+    bans: HashMap<String, i64>,
+    /// Configured rather than stored.
+    #[serde(skip, default = "default_ban_duration")]
+    ban_duration: i64,
+}
+
+impl Default for BanList {
+    fn default() -> Self {
+        BanList::new(DEFAULT_BAN_DURATION)
+    }
+}
+
+impl BanList {
+    pub fn new(ban_duration: i64) -> Self {
+        BanList {
+            scores: HashMap::new(),
+            bans: HashMap::new(),
+            ban_duration,
+        }
+    }
+
+    pub fn set_ban_duration(&mut self, ban_duration: i64) {
+        self.ban_duration = ban_duration;
+    }
+
+    pub fn score(&self, address: &str) -> i32 {
+        ban_key(address)
+            .and_then(|key| self.scores.get(key))
+            .map_or(0, |score| score.at(Utc::now().timestamp()))
+    }
+
+    /// Drop the score of the peer by the misbehavior, and ban the peer if it crosses the threshold.
+    /// Return true if the peer is banned just now.
+    pub fn penalize(&mut self, address: &str, misbehavior: Misbehavior) -> bool {
+        let key = match ban_key(address) {
+            Some(key) => key,
+            None => {
+                debug!(
+                    "peer {} misbehaves: {:?}, unknown port.",
+                    address, misbehavior
+                );
+                return false;
+            }
+        };
+        let now = Utc::now().timestamp();
+        self.bans.retain(|_, until| *until > now);
+        if self.bans.contains_key(key) {
+            return false;
+        }
+        let score = self.score(key) - misbehavior.penalty();
+        debug!(
+            "peer {} misbehaves: {:?}, score {}.",
+            key, misbehavior, score
+        );
+        if score > BAN_THRESHOLD {
+            if !self.scores.contains_key(key) {
+                self.evict_scores(now);
+            }
+            self.scores.insert(
+                key.to_string(),
+                Score {
+                    value: score,
+                    penalized: now,
+                },
+            );
+            return false;
+        }
+        self.scores.remove(key);
+        self.bans.insert(key.to_string(), now + self.ban_duration);
+        true
+    }
+
+    /// Make room for a new score, evict recovered scores first, then the least recently penalized.
+    fn evict_scores(&mut self, now: i64) {
+        if self.scores.len() < MAX_SCORED_HOSTS {
+            return;
+        }
+        self.scores.retain(|_, score| score.at(now) < 0);
+        while self.scores.len() >= MAX_SCORED_HOSTS {
+            let oldest = self
+                .scores
+                .iter()
+                .min_by_key(|&(_, score)| score.penalized)
+                .map(|(host, _)| host.clone());
+            match oldest {
+                Some(host) => self.scores.remove(&host),
+                None => break,
+            };
+        }
+    }
+
+    /// Check if the peer of the address is banned.
+    pub fn is_banned(&self, address: &str) -> bool {
+        ban_key(address)
+            .and_then(|key| self.bans.get(key))
+            .map_or(false, |&until| until > Utc::now().timestamp())
+    }
+
+    /// Lift the ban of the peer of the address, return false if it's not banned.
+    pub fn unban(&mut self, address: &str) -> bool {
+        let banned = self.is_banned(address);
+        if let Some(key) = ban_key(address) {
+            self.bans.remove(key);
+        }
+        banned
+    }
+
+    /// Hosts or local addresses currently banned, ordered by address.
+    pub fn banned(&self) -> Vec<Ban> {
+        let now = Utc::now().timestamp();
+        let mut banned: Vec<Ban> = self
+            .bans
+            .iter()
+            .filter(|&(_, &until)| until > now)
+            .map(|(address, &until)| Ban {
+                address: address.clone(),
+                until,
+            })
+            .collect();
+        banned.sort_by(|a, b| a.address.cmp(&b.address));
+        banned
+    }
+}
+
+#[cfg(test)]
+mod test_ban {
+    use super::*;
+
+    #[test]
+    fn test_ban_after_misbehaving() {
+        let mut bans = BanList::default();
+        assert!(!bans.penalize("8.8.8.8:9111", Misbehavior::InvalidBlocks));
+        assert!(!bans.penalize("8.8.8.8:9111", Misbehavior::Timeout));
+        assert_eq!(-60, bans.score("8.8.8.8:9111"));
+        assert!(!bans.is_banned("8.8.8.8:9111"));
+
+        assert!(bans.penalize("8.8.8.8:9111", Misbehavior::InvalidBlocks));
+        assert!(bans.is_banned("8.8.8.8:9111"));
+        // Other ports of the host are banned as well.
+        assert!(bans.is_banned("8.8.8.8:9222"));
+        assert!(bans.is_banned("8.8.8.8"));
+        assert!(!bans.is_banned("8.8.4.4:9111"));
+        assert!(!bans.penalize("8.8.8.8:9111", Misbehavior::InvalidBlocks));
+        assert_eq!(0, bans.score("8.8.8.8:9111"));
+        assert_eq!(1, bans.banned().len());
+
+        assert!(bans.unban("8.8.8.8:9111"));
+        assert!(!bans.unban("8.8.8.8:9111"));
+        assert!(bans.banned().is_empty());
+    }
+
+    #[test]
+    fn test_ban_local_address() {
+        let mut bans = BanList::default();
+        for _ in 0..2 {
+            bans.penalize("127.0.0.1:9111", Misbehavior::InvalidBlocks);
+        }
+        assert!(bans.is_banned("127.0.0.1:9111"));
+        // Other nodes of the local host are not banned.
+        assert!(!bans.is_banned("127.0.0.1:9222"));
+        assert!(!bans.is_banned("127.0.0.1"));
+        assert!(!bans.penalize("127.0.0.1", Misbehavior::InvalidBlocks));
+        assert_eq!(1, bans.banned().len());
+        assert_eq!("127.0.0.1:9111", bans.banned()[0].address);
+
+        assert_eq!(Some("[fd00::1]:9111"), ban_key("[fd00::1]:9111"));
+        assert_eq!(Some("192.168.1.2:9111"), ban_key("192.168.1.2:9111"));
+        assert_eq!(Some("2001:db8::1"), ban_key("[2001:db8::1]:9111"));
+        assert_eq!(Some("localhost"), ban_key("localhost:9111"));
+        assert_eq!(None, ban_key("10.0.0.1"));
+    }
+
+    #[test]
+    fn test_ban_expires() {
+        let mut bans = BanList::new(-1);
+        for _ in 0..5 {
+            bans.penalize("127.0.0.1:9111", Misbehavior::MalformedMessage);
+        }
+        assert!(!bans.is_banned("127.0.0.1:9111"));
+        assert!(bans.banned().is_empty());
+    }
+
+    #[test]
+    fn test_score_recovers() {
+        let now = Utc::now().timestamp();
+        let mut bans = BanList::default();
+        bans.scores.insert(
+            "8.8.8.8".to_string(),
+            Score {
+                value: -90,
+                penalized: now - 60 * SCORE_RECOVERY_INTERVAL,
+            },
+        );
+        assert_eq!(-30, bans.score("8.8.8.8:9111"));
+        assert!(!bans.penalize("8.8.8.8:9111", Misbehavior::InvalidBlocks));
+        assert_eq!(-80, bans.score("8.8.8.8:9111"));
+
+        bans.scores.get_mut("8.8.8.8").unwrap().penalized = 0;
+        assert_eq!(0, bans.score("8.8.8.8:9111"));
+    }
+
+    #[test]
+    fn test_host_of() {
+        assert_eq!("::1", host_of("[::1]:9111"));
+        assert_eq!("::1", host_of("::1"));
+        assert_eq!("localhost", host_of("localhost:9111"));
+    }
+
+    #[test]
+    fn test_cap_scores() {
+        let mut bans = BanList::default();
+        for i in 0..MAX_SCORED_HOSTS + 10 {
+            bans.penalize(
+                &format!("10.0.{}.{}:9111", i / 256, i % 256),
+                Misbehavior::Timeout,
+            );
+        }
+        assert_eq!(MAX_SCORED_HOSTS, bans.scores.len());
+    }
+}
@@ -0,0 +1,104 @@
+//! The discovery module exchanges peer lists with neighbors periodically in background,
+//! so a node joins the whole network by knowing any one of its nodes.
+//!
+//! Peers of neighbors are pulled via `/list_peers`, along with own listening port,
+//! so neighbors learn about this node as well.
+
+use super::{http_get, with_timeout, Peers, LISTENED_PORT};
+use futures::{future, Future, Stream};
+use serde_json;
+use std::io;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio_core::reactor::{Core, Handle, Interval};
+
+/// Seconds between two rounds of exchanging peers, if not configured.
+pub const DEFAULT_DISCOVERY_INTERVAL: u64 = 30;
+
+/// Seconds waiting for a neighbor to respond its peers.
+const DISCOVERY_TIMEOUT: u64 = 5;
+
+pub struct PeerDiscovery {
+    peers: Arc<RwLock<Peers>>,
+}
+
+impl PeerDiscovery {
+    pub fn new(peers: Arc<RwLock<Peers>>) -> Self {
+        PeerDiscovery { peers }
+    }
+
+    /// Spawn a thread running its own reactor, which exchanges peers every interval.
+    /// Only live neighbors are exchanged with, so seed nodes are reached once
+    /// the prober has handshaked with them.
+    pub fn spawn(self, interval: Duration) {
+        thread::spawn(move || {
+            let mut core = Core::new().expect("failed to create the discovery reactor.");
+            let handle = core.handle();
+            let ticks = Interval::new_at(Instant::now(), interval, &handle)
+                .expect("failed to create the interval.");
+            let discovery_future = ticks.for_each(|_| self.exchange(handle.clone()));
+            core.run(discovery_future).expect("peer discovery stopped.");
+        });
+    }
+
+    /// Pull peers from all live neighbors, and merge them into own peers.
+    fn exchange(&self, handle: Handle) -> Box<Future<Item = (), Error = io::Error>> {
+        let neighbors = self.peers.read().unwrap().live_addresses();
+        let exchange_futures: Vec<_> = neighbors
+            .into_iter()
+            .map(|neighbor| {
+                let url = format!("http://{}/list_peers?port={}", neighbor, *LISTENED_PORT);
+                let cloned_peers = self.peers.clone();
+                with_timeout(&handle, http_get(&handle, &url), DISCOVERY_TIMEOUT).then(
+                    move |result| {
+                        let discovered =
+                            result.map_err(|err| err.to_string()).and_then(|content| {
+                                serde_json::from_slice::<Peers>(&content)
+                                    .map_err(|err| err.to_string())
+                            });
+                        match discovered {
+                            Ok(discovered) => {
+                                Peers::compare_and_update(cloned_peers, discovered.unverified())
+                            }
+                            Err(err) => debug!("exchange peers with {} failed: {}", neighbor, err),
+                        }
+                        Ok(())
+                    },
+                )
+            })
+            .collect();
+        Box::new(future::join_all(exchange_futures).map(|_| ()))
+    }
+}
+
+#[cfg(test)]
+mod test_discovery {
+    use super::super::{Liveness, Peer};
+    use super::*;
+
+    #[test]
+    fn test_merge_discovered_peers() {
+        let mut own_peers = Peers::new();
+        own_peers
+            .carriers
+            .push(Peer::new("127.0.0.1:9111", Liveness::Live));
+        let own_peers = Arc::new(RwLock::new(own_peers));
+
+        let mut discovered = Peers::new();
+        for address in ["127.0.0.1:9111", "127.0.0.1:9222", "127.0.0.1:9222"].iter() {
+            let mut peer = Peer::new(*address, Liveness::Failed);
+            peer.failures = 2;
+            discovered.carriers.push(peer);
+        }
+        Peers::compare_and_update(own_peers.clone(), discovered.unverified());
+
+        let own_peers = own_peers.read().unwrap();
+        assert_eq!(2, own_peers.carriers.len());
+        assert_eq!(Liveness::Live, own_peers.carriers[0].liveness);
+        assert_eq!(
+            &Peer::new("127.0.0.1:9222", Liveness::Unknown),
+            &own_peers.carriers[1]
+        );
+    }
+}
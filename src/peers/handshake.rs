@@ -0,0 +1,246 @@
+//! The handshake module verifies a peer is a compatible node before it's marked live.
+//!
+//! Both nodes send their handshake, and check the other one runs the same protocol
+//! version on the same network, i.e. the same network id and genesis block.
+
+use super::ban::Misbehavior;
+use super::liveness::PROBE_TIMEOUT;
+use super::{is_timeout, with_timeout, Peers, LISTENED_PORT};
+use blockchain::blockchain::BlockChain;
+use chrono::Utc;
+use futures::{future, Future, Stream};
+use hex;
+use hyper::header::{ContentLength, ContentType};
+use hyper::{self, Client, Method, Request, StatusCode, Uri};
+use serde_json;
+use sha3::{Digest, Sha3_256};
+use std::fmt;
+use std::io;
+use std::sync::{Arc, RwLock};
+use tokio_core::reactor::Handle;
+
+/// Version of the protocol between nodes, nodes of different versions are incompatible.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Services provided to peers by this node.
+pub const CAPABILITIES: [&str; 4] = [
+    "headers_sync",
+    "publish_blocks",
+    "publish_tx",
+    "peer_exchange",
+];
+
+lazy_static! {
+    /// Random id of this node generated on startup, to detect connecting to self.
+    pub static ref NODE_ID: String = {
+        let mut hasher = Sha3_256::default();
+        hasher.input(format!("{}-{}", Utc::now().timestamp_nanos(), *LISTENED_PORT).as_bytes());
+        hex::encode(&hasher.result().as_slice()[..16])
+    };
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Handshake {
+    pub protocol_version: u32,
+    pub network_id: String,
+    /// Hex encoded hash of the genesis block.
+    pub genesis_hash: String,
+    pub node_id: String,
+    /// The address the node listens on, peers reach it at the port.
+    pub listening_address: String,
+    pub tip_height: u32,
+    pub capabilities: Vec<String>,
+}
+
+impl Handshake {
+    /// Check the handshake of a peer is compatible with own one, return the reason if not.
+    pub fn check_compatible(&self, own: &Handshake) -> Result<(), String> {
+        if self.protocol_version != own.protocol_version {
+            Err(format!(
+                "protocol version {} mismatch, expect {}.",
+                self.protocol_version, own.protocol_version
+            ))
+        } else if self.network_id != own.network_id {
+            Err(format!("network id {} mismatch.", self.network_id))
+        } else if self.genesis_hash != own.genesis_hash {
+            Err("genesis block mismatch.".to_string())
+        } else if self.node_id == own.node_id {
+            Err("connected to self.".to_string())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The port of the listening address.
+    pub fn listening_port(&self) -> Option<u32> {
+        self.listening_address
+            .rsplit(':')
+            .next()
+            .and_then(|port| port.parse().ok())
+    }
+}
+
+#[derive(Debug)]
+pub enum HandshakeError {
+    /// The peer is incompatible with this node.
+    Rejected(String),
+    /// The peer refuses the handshake for other reasons, e.g. it bans this node or fails,
+    /// which may not last.
+    Refused(StatusCode),
+    /// The peer can't be reached.
+    Unreachable(hyper::Error),
+}
+
+impl fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HandshakeError::Rejected(ref reason) => write!(f, "rejected, {}", reason),
+            HandshakeError::Refused(status) => write!(f, "refused, {}", status),
+            HandshakeError::Unreachable(ref err) => write!(f, "unreachable, {}", err),
+        }
+    }
+}
+
+/// Where own handshake is made from.
+pub struct LocalNode {
+    block_chain: Arc<RwLock<BlockChain>>,
+    network_id: String,
+}
+
+impl LocalNode {
+    pub fn new(block_chain: Arc<RwLock<BlockChain>>, network_id: String) -> Self {
+        LocalNode {
+            block_chain,
+            network_id,
+        }
+    }
+
+    pub fn handshake(&self) -> Handshake {
+        let block_chain = self.block_chain.read().unwrap();
+        Handshake {
+            protocol_version: PROTOCOL_VERSION,
+            network_id: self.network_id.clone(),
+            genesis_hash: hex::encode(block_chain.genesis_hash()),
+            node_id: NODE_ID.clone(),
+            listening_address: format!("0.0.0.0:{}", *LISTENED_PORT),
+            tip_height: block_chain.len() as u32 - 1,
+            capabilities: CAPABILITIES.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+}
+
+/// Send own handshake to the peer, and check the handshake it responds.
+pub fn shake_hands(
+    handle: &Handle,
+    address: &str,
+    own: Handshake,
+) -> Box<Future<Item = Handshake, Error = HandshakeError>> {
+    let url: Uri = match format!("http://{}/handshake", address).parse() {
+        Ok(url) => url,
+        Err(err) => return Box::new(future::err(HandshakeError::Unreachable(err.into()))),
+    };
+    let content = serde_json::to_vec(&own).unwrap();
+    let mut request = Request::new(Method::Post, url);
+    request.headers_mut().set(ContentType::json());
+    request
+        .headers_mut()
+        .set(ContentLength(content.len() as u64));
+    request.set_body(content);
+    let response = Client::new(handle).request(request).and_then(|response| {
+        let status = response.status();
+        response
+            .body()
+            .concat2()
+            .map(move |body| (status, body.to_vec()))
+    });
+    Box::new(
+        with_timeout(handle, Box::new(response), PROBE_TIMEOUT)
+            .map_err(HandshakeError::Unreachable)
+            .and_then(move |(status, body)| {
+                // Incompatible handshakes are responded with bad request.
+                if status == StatusCode::BadRequest {
+                    return Err(HandshakeError::Rejected(
+                        String::from_utf8_lossy(&body).into_owned(),
+                    ));
+                }
+                if status != StatusCode::Ok {
+                    return Err(HandshakeError::Refused(status));
+                }
+                let handshake: Handshake = serde_json::from_slice(&body)
+                    .map_err(|_| HandshakeError::Rejected("invalid handshake.".to_string()))?;
+                handshake
+                    .check_compatible(&own)
+                    .map_err(HandshakeError::Rejected)?;
+                Ok(handshake)
+            }),
+    )
+}
+
+/// Handshake with peers of the addresses in parallel, and record results when all of them are done.
+/// Peers timing out are penalized.
+pub fn handshake_with_peers(
+    handle: &Handle,
+    peers: Arc<RwLock<Peers>>,
+    local: &LocalNode,
+    addresses: Vec<String>,
+    max_failures: u32,
+) -> Box<Future<Item = (), Error = io::Error>> {
+    let own = local.handshake();
+    let handshake_futures: Vec<_> = addresses
+        .into_iter()
+        .map(|address| {
+            shake_hands(handle, &address, own.clone()).then(move |result| {
+                if let Err(ref err) = result {
+                    debug!("handshake with {} failed: {}", address, err);
+                }
+                Ok((address, result))
+            })
+        })
+        .collect();
+    Box::new(future::join_all(handshake_futures).map(move |results| {
+        let mut peers = peers.write().unwrap();
+        for (address, result) in results {
+            if let Err(HandshakeError::Unreachable(ref err)) = result {
+                if is_timeout(err) {
+                    peers.penalize(&address, Misbehavior::Timeout);
+                }
+            }
+            peers.record_handshake(&address, &result, max_failures);
+        }
+    }))
+}
+
+#[cfg(test)]
+mod test_handshake {
+    use super::*;
+
+    fn handshake(node_id: &str) -> Handshake {
+        Handshake {
+            protocol_version: PROTOCOL_VERSION,
+            network_id: "bam-chain".to_string(),
+            genesis_hash: "00ab".to_string(),
+            node_id: node_id.to_string(),
+            listening_address: "0.0.0.0:8181".to_string(),
+            tip_height: 0,
+            capabilities: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_check_compatible() {
+        let own = handshake("own");
+        assert!(handshake("peer").check_compatible(&own).is_ok());
+        assert_eq!(Some(8181), handshake("peer").listening_port());
+        assert!(handshake("own").check_compatible(&own).is_err());
+
+        let mut other_version = handshake("peer");
+        other_version.protocol_version += 1;
+        assert!(other_version.check_compatible(&own).is_err());
+        let mut other_network = handshake("peer");
+        other_network.network_id = "other".to_string();
+        assert!(other_network.check_compatible(&own).is_err());
+        let mut other_genesis = handshake("peer");
+        other_genesis.genesis_hash = "00cd".to_string();
+        assert!(other_genesis.check_compatible(&own).is_err());
+    }
+}
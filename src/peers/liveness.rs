@@ -0,0 +1,89 @@
+//! The liveness module probes all peers periodically in background by handshaking with them,
+//! so only compatible and reachable peers are live, and failed peers are removed after failing repeatedly.
+//! Peers are saved to the peer store after every round, if any.
+
+use super::handshake::{handshake_with_peers, LocalNode};
+use super::store::PeerStore;
+use super::Peers;
+use futures::{Future, Stream};
+use std::io;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+use tokio_core::reactor::{Core, Handle, Interval};
+
+/// Seconds between two rounds of probing, if not configured.
+pub const DEFAULT_PROBE_INTERVAL: u64 = 10;
+
+/// Failed probes in a row before a peer is removed, if not configured.
+pub const DEFAULT_MAX_PEER_FAILURES: u32 = 3;
+
+/// Seconds waiting for a peer to respond a probe.
+pub const PROBE_TIMEOUT: u64 = 5;
+
+pub struct LivenessProber {
+    peers: Arc<RwLock<Peers>>,
+    local: Arc<LocalNode>,
+    max_failures: u32,
+    store: Option<Arc<PeerStore>>,
+}
+
+impl LivenessProber {
+    pub fn new(
+        peers: Arc<RwLock<Peers>>,
+        local: Arc<LocalNode>,
+        max_failures: u32,
+        store: Option<Arc<PeerStore>>,
+    ) -> Self {
+        LivenessProber {
+            peers,
+            local,
+            max_failures,
+            store,
+        }
+    }
+
+    /// Spawn a thread running its own reactor, which probes all peers every interval.
+    pub fn spawn(self, interval: Duration) {
+        thread::spawn(move || {
+            let mut core = Core::new().expect("failed to create the prober reactor.");
+            let handle = core.handle();
+            let ticks = Interval::new(interval, &handle).expect("failed to create the interval.");
+            let probe_future = ticks.for_each(|_| self.probe(handle.clone()));
+            core.run(probe_future).expect("liveness prober stopped.");
+        });
+    }
+
+    /// Handshake with all peers, record results and save peers when all of them are done.
+    /// Failing to save peers doesn't stop probing.
+    fn probe(&self, handle: Handle) -> Box<Future<Item = (), Error = io::Error>> {
+        let addresses: Vec<String> = self
+            .peers
+            .read()
+            .unwrap()
+            .carriers
+            .iter()
+            .map(|peer| peer.address.clone())
+            .collect();
+        let cloned_peers = self.peers.clone();
+        let cloned_store = self.store.clone();
+        Box::new(
+            handshake_with_peers(
+                &handle,
+                self.peers.clone(),
+                &self.local,
+                addresses,
+                self.max_failures,
+            )
+            .map(move |_| {
+                if let Some(store) = cloned_store {
+                    // Save a snapshot, so the lock isn't held while writing.
+                    let snapshot = cloned_peers.read().unwrap().clone();
+                    if let Err(err) = store.save(&snapshot) {
+                        warn!("failed to save peers: {}", err);
+                    }
+                }
+            }),
+        )
+    }
+}
@@ -0,0 +1,742 @@
+//! The peer module builds metadata for tracking all nodes.
+
+pub mod ban;
+pub mod discovery;
+pub mod handshake;
+pub mod liveness;
+pub mod store;
+
+use self::ban::{BanList, Misbehavior, DEFAULT_BAN_DURATION};
+use self::discovery::{PeerDiscovery, DEFAULT_DISCOVERY_INTERVAL};
+use self::handshake::{handshake_with_peers, Handshake, HandshakeError, LocalNode};
+use self::liveness::{LivenessProber, DEFAULT_MAX_PEER_FAILURES, DEFAULT_PROBE_INTERVAL};
+use self::store::PeerStore;
+use blockchain::blockchain_sync::SyncBlockMessage;
+use chrono::Utc;
+use cli::ARGS;
+use config::NODE_CONFIG;
+use extractors::{AddressPath, ListPeersQuery};
+use futures::{future, Future, Stream};
+use gotham::handler::{HandlerFuture, IntoHandlerError};
+use gotham::http::response::create_response;
+use gotham::state::{client_addr, FromState, State};
+use hyper::header::{ContentLength, ContentType};
+use hyper::{self, Body, Client, Method, Request, Response, StatusCode, Uri};
+use mime::{APPLICATION_JSON, TEXT_PLAIN};
+use serde_json;
+use std::io::{self, ErrorKind};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio_core::reactor::{Handle, Timeout};
+
+lazy_static! {
+    pub static ref LISTENED_PORT: u32 = ARGS.port;
+    static ref LOCAL_HOST: String = format!("{}:{}", "localhost", *LISTENED_PORT);
+    static ref LOCAL_HOST_IP: String = format!("{}:{}", "127.0.0.1", *LISTENED_PORT);
+    static ref LOOP_BACK_IP: String = format!("{}:{}", "0.0.0.0", *LISTENED_PORT);
+}
+
+/// Maximum number of peers kept in memory, further peers are ignored.
+pub const MAX_PEERS: usize = 1000;
+
+/// Helper function to check if the peer address is self.
+fn check_is_self_address(peer: &Peer) -> bool {
+    peer.address == *LOCAL_HOST || peer.address == *LOCAL_HOST_IP || peer.address == *LOOP_BACK_IP
+}
+
+/// Check if the address is in the form of host:port, so it can be requested as a peer.
+fn is_valid_address(address: &str) -> bool {
+    let mut parts = address.rsplitn(2, ':');
+    let port = parts.next().and_then(|port| port.parse::<u16>().ok());
+    let host = parts.next().unwrap_or("");
+    let valid_host = if host.starts_with('[') && host.ends_with(']') {
+        host[1..host.len() - 1].parse::<Ipv6Addr>().is_ok()
+    } else {
+        !host.is_empty()
+            && host
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+    };
+    valid_host && port.map_or(false, |port| port > 0)
+}
+
+/// The address of the node listening on the port at ip, IPv6 hosts are bracketed.
+/// Return None if the port is out of range.
+pub fn peer_address(ip: IpAddr, port: u32) -> Option<String> {
+    if port > u32::from(u16::max_value()) {
+        return None;
+    }
+    Some(SocketAddr::new(ip, port as u16).to_string())
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Liveness {
+    Live,
+    Failed,
+    Unknown,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Peer {
+    pub address: String,
+    pub liveness: Liveness,
+    /// Unix timestamp of the last successful probe.
+    #[serde(default)]
+    pub last_seen: Option<i64>,
+    /// Failed probes in a row.
+    #[serde(default)]
+    pub failures: u32,
+    /// The last handshake of the peer, recording its capabilities.
+    #[serde(default)]
+    pub handshake: Option<Handshake>,
+}
+
+impl Peer {
+    pub fn new<S: Into<String>>(address: S, liveness: Liveness) -> Self {
+        Peer {
+            address: address.into(),
+            liveness,
+            last_seen: None,
+            failures: 0,
+            handshake: None,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Peers {
+    pub carriers: Vec<Peer>,
+    /// Scores and bans of peers, which are kept locally.
+    #[serde(skip)]
+    pub bans: BanList,
+}
+
+impl From<Vec<Peer>> for Peers {
+    fn from(carriers: Vec<Peer>) -> Self {
+        Peers {
+            carriers,
+            bans: BanList::default(),
+        }
+    }
+}
+
+impl Peers {
+    pub fn new() -> Peers {
+        Peers::from(Vec::new())
+    }
+
+    /// Compare peers in self held peers and the new coming peer list.
+    /// If any update, push it into original peer.
+    /// Peers are compared by address, their liveness is checked by the prober later.
+    /// Banned peers and invalid addresses are never added, and at most `MAX_PEERS` peers are kept.
+    pub fn compare_and_update(self_peers: Arc<RwLock<Peers>>, other_peers: Peers) {
+        // Find the peers that existed or not.
+        // If not existed, push into extension vector.
+        let mut extension: Vec<Peer> = Vec::new();
+        {
+            let own_peers = self_peers.write().unwrap();
+            for carrier in other_peers.carriers.into_iter() {
+                if own_peers.carriers.len() + extension.len() >= MAX_PEERS {
+                    debug!("too many peers, ignore the rest.");
+                    break;
+                }
+                if check_is_self_address(&carrier)
+                    || !is_valid_address(&carrier.address)
+                    || own_peers.bans.is_banned(&carrier.address)
+                {
+                    continue;
+                }
+                let existed = own_peers
+                    .carriers
+                    .iter()
+                    .chain(extension.iter())
+                    .any(|p| p.address == carrier.address);
+                if !existed {
+                    extension.push(carrier);
+                }
+            }
+        }
+
+        let mut own_peers = self_peers.write().unwrap();
+        own_peers.carriers.extend(extension);
+    }
+
+    /// Addresses of peers which are handshaked and reachable, and not banned.
+    pub fn live_addresses(&self) -> Vec<String> {
+        self.carriers
+            .iter()
+            .filter(|peer| peer.liveness == Liveness::Live && !self.bans.is_banned(&peer.address))
+            .map(|peer| peer.address.clone())
+            .collect()
+    }
+
+    /// Peers observed by others, whose liveness is unknown until handshaking with them.
+    pub fn unverified(self) -> Peers {
+        Peers::from(
+            self.carriers
+                .into_iter()
+                .map(|peer| Peer::new(peer.address, Liveness::Unknown))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    /// Drop the score of the peer for its misbehavior, banned peers are removed once it's banned,
+    /// i.e. peers of its host, or only itself on a local host.
+    pub fn penalize(&mut self, address: &str, misbehavior: Misbehavior) {
+        if self.bans.penalize(address, misbehavior) {
+            info!("ban peer {} for misbehaving.", address);
+            let bans = &self.bans;
+            self.carriers.retain(|peer| !bans.is_banned(&peer.address));
+        }
+    }
+
+    /// Record the result of handshaking with the peer, the peer is live only if it's compatible.
+    /// An incompatible peer is removed, an unreachable or refusing one is removed after
+    /// max failures in a row, or never if max failures is zero.
+    pub fn record_handshake(
+        &mut self,
+        address: &str,
+        result: &Result<Handshake, HandshakeError>,
+        max_failures: u32,
+    ) {
+        if let Err(HandshakeError::Rejected(ref reason)) = *result {
+            info!("remove incompatible peer {}: {}", address, reason);
+            self.carriers.retain(|peer| peer.address != address);
+            return;
+        }
+        if let Some(peer) = self.carriers.iter_mut().find(|p| p.address == address) {
+            match *result {
+                Ok(ref handshake) => {
+                    peer.liveness = Liveness::Live;
+                    peer.last_seen = Some(Utc::now().timestamp());
+                    peer.failures = 0;
+                    peer.handshake = Some(handshake.clone());
+                }
+                Err(_) => {
+                    peer.liveness = Liveness::Failed;
+                    peer.failures += 1;
+                }
+            }
+        }
+        self.carriers.retain(|peer| {
+            let keep = max_failures == 0 || peer.failures < max_failures;
+            if !keep {
+                info!(
+                    "remove peer {} after {} failures.",
+                    peer.address, peer.failures
+                );
+            }
+            keep
+        });
+    }
+}
+
+pub struct PeerAPIs {
+    peers: Arc<RwLock<Peers>>,
+    local: Arc<LocalNode>,
+    max_failures: u32,
+    /// Where peers are saved, none if peers are kept in memory only.
+    store: Option<Arc<PeerStore>>,
+}
+
+impl PeerAPIs {
+    /// Peers are reloaded from the configured peer store, if any.
+    pub fn new(local: LocalNode) -> Self {
+        let store = NODE_CONFIG.open_peer_store();
+        let mut peers = match store {
+            Some(ref store) => store.load().unwrap_or_else(|err| {
+                warn!("failed to load stored peers: {}", err);
+                Peers::new()
+            }),
+            None => Peers::new(),
+        };
+        peers
+            .bans
+            .set_ban_duration(NODE_CONFIG.ban_duration.unwrap_or(DEFAULT_BAN_DURATION));
+        PeerAPIs {
+            peers: Arc::new(RwLock::new(peers)),
+            local: Arc::new(local),
+            max_failures: NODE_CONFIG
+                .max_peer_failures
+                .unwrap_or(DEFAULT_MAX_PEER_FAILURES),
+            store: store.map(Arc::new),
+        }
+    }
+
+    /// Add peers from carried known peers via request.
+    /// Peers are handshaked before responding, and only compatible ones become live.
+    /// Response BadRequest if the peers can't be parsed.
+    pub fn add_peers(&self, mut state: State) -> Box<HandlerFuture> {
+        let handle = Handle::borrow_from(&mut state).clone();
+        let cloned_peers = self.peers.clone();
+        let cloned_local = self.local.clone();
+        let max_failures = self.max_failures;
+        let parse_future = Body::take_from(&mut state)
+            .concat2()
+            .then(move |full_body| match full_body {
+                Ok(valid_body) => {
+                    let other_peers = match serde_json::from_slice::<Peers>(&valid_body.to_vec()) {
+                        Ok(other_peers) => other_peers.unverified(),
+                        Err(_) => {
+                            let res = create_response(&state, StatusCode::BadRequest, None);
+                            return future::Either::B(future::ok((state, res)));
+                        }
+                    };
+                    let addresses = other_peers
+                        .carriers
+                        .iter()
+                        .filter(|peer| {
+                            !check_is_self_address(peer) && is_valid_address(&peer.address)
+                        })
+                        .map(|peer| peer.address.clone())
+                        .collect();
+                    Peers::compare_and_update(cloned_peers.clone(), other_peers);
+                    future::Either::A(
+                        handshake_with_peers(
+                            &handle,
+                            cloned_peers,
+                            &cloned_local,
+                            addresses,
+                            max_failures,
+                        )
+                        .then(|_| {
+                            let res = create_response(&state, StatusCode::Ok, None);
+                            future::ok((state, res))
+                        }),
+                    )
+                }
+                Err(e) => future::Either::B(future::err((state, e.into_handler_error()))),
+            });
+        Box::new(parse_future)
+    }
+
+    /// Check the handshake of the requesting node, and response own handshake if it's compatible.
+    /// The requesting node is added as a peer reached at the port of its listening address,
+    /// it becomes live once the prober handshakes with it.
+    /// Response BadRequest with the reason if it's incompatible, and Forbidden if it's banned.
+    pub fn handshake(&self, mut state: State) -> Box<HandlerFuture> {
+        let requester_ip = client_addr(&state).map(|addr| addr.ip());
+        let cloned_peers = self.peers.clone();
+        let cloned_local = self.local.clone();
+        let parse_future = Body::take_from(&mut state)
+            .concat2()
+            .then(move |full_body| {
+                let body = match full_body {
+                    Ok(body) => body,
+                    Err(e) => return future::err((state, e.into_handler_error())),
+                };
+                let own = cloned_local.handshake();
+                let checked = serde_json::from_slice::<Handshake>(&body.to_vec())
+                    .map_err(|_| "invalid handshake.".to_string())
+                    .and_then(|handshake| {
+                        handshake.check_compatible(&own)?;
+                        Ok(handshake)
+                    });
+                let requester = checked.as_ref().ok().and_then(|handshake| {
+                    match (requester_ip, handshake.listening_port()) {
+                        (Some(ip), Some(port)) => peer_address(ip, port),
+                        _ => None,
+                    }
+                });
+                let banned = requester_ip.map_or(false, |ip| {
+                    let address = requester.clone().unwrap_or_else(|| ip.to_string());
+                    cloned_peers.read().unwrap().bans.is_banned(&address)
+                });
+                let res = match checked {
+                    _ if banned => {
+                        debug!("reject handshake: {} is banned.", requester_ip.unwrap());
+                        create_response(&state, StatusCode::Forbidden, None)
+                    }
+                    Ok(_) => {
+                        if let Some(address) = requester {
+                            let requester =
+                                Peers::from(vec![Peer::new(address, Liveness::Unknown)]);
+                            Peers::compare_and_update(cloned_peers.clone(), requester);
+                        }
+                        let body = serde_json::to_vec(&own).unwrap();
+                        create_response(&state, StatusCode::Ok, Some((body, APPLICATION_JSON)))
+                    }
+                    Err(reason) => {
+                        debug!("reject handshake: {}", reason);
+                        create_response(
+                            &state,
+                            StatusCode::BadRequest,
+                            Some((reason.into_bytes(), TEXT_PLAIN)),
+                        )
+                    }
+                };
+                future::ok((state, res))
+            });
+        Box::new(parse_future)
+    }
+
+    /// List own peers, the requesting node is added as a peer if it tells its listening port.
+    /// A banned requester is responded Forbidden.
+    pub fn list_peers(&self, mut state: State) -> (State, Response) {
+        let ListPeersQuery { port } = ListPeersQuery::take_from(&mut state);
+        if let Some(res) = self.reject_banned(&state, port) {
+            return (state, res);
+        }
+        let cloned_peers = self.peers.clone();
+        let res = create_response(
+            &state,
+            StatusCode::Ok,
+            Some((
+                serde_json::to_vec(&*cloned_peers.read().unwrap()).unwrap(),
+                APPLICATION_JSON,
+            )),
+        );
+        let requester = match (port, client_addr(&state)) {
+            (Some(port), Some(addr)) => peer_address(addr.ip(), port),
+            _ => None,
+        };
+        if let Some(address) = requester {
+            let requester = Peers::from(vec![Peer::new(address, Liveness::Unknown)]);
+            Peers::compare_and_update(cloned_peers, requester);
+        }
+        (state, res)
+    }
+
+    /// Spawn the background prober with the configured interval, which also saves peers.
+    pub fn spawn_prober(&self) {
+        let interval = NODE_CONFIG
+            .peer_probe_interval
+            .unwrap_or(DEFAULT_PROBE_INTERVAL);
+        LivenessProber::new(
+            self.peers.clone(),
+            self.local.clone(),
+            self.max_failures,
+            self.store.clone(),
+        )
+        .spawn(Duration::from_secs(interval));
+    }
+
+    /// Add the configured seed nodes, and spawn the background discovery with the configured interval.
+    pub fn spawn_discovery(&self) {
+        let seeds = Peers::from(
+            NODE_CONFIG
+                .seeds
+                .iter()
+                .map(|seed| Peer::new(seed.as_str(), Liveness::Unknown))
+                .collect::<Vec<_>>(),
+        );
+        Peers::compare_and_update(self.peers.clone(), seeds);
+        let interval = NODE_CONFIG
+            .discovery_interval
+            .unwrap_or(DEFAULT_DISCOVERY_INTERVAL);
+        PeerDiscovery::new(self.peers.clone()).spawn(Duration::from_secs(interval));
+    }
+
+    /// Addresses of all live peers.
+    pub fn peer_addresses(&self) -> Vec<String> {
+        self.peers.read().unwrap().live_addresses()
+    }
+
+    /// Drop the score of the peer for its misbehavior, and ban it once the score is too low.
+    pub fn penalize(&self, address: &str, misbehavior: Misbehavior) {
+        self.peers.write().unwrap().penalize(address, misbehavior);
+    }
+
+    pub fn is_banned(&self, address: &str) -> bool {
+        self.peers.read().unwrap().bans.is_banned(address)
+    }
+
+    /// Response Forbidden if the requesting peer is banned, it's checked by its listening port
+    /// if known, otherwise by its host, so nodes of a local host are not told apart.
+    pub fn reject_banned(&self, state: &State, port: Option<u32>) -> Option<Response> {
+        let ip = client_addr(state)?.ip();
+        let address = port
+            .and_then(|port| peer_address(ip, port))
+            .unwrap_or_else(|| ip.to_string());
+        if !self.is_banned(&address) {
+            return None;
+        }
+        debug!("reject the request from banned peer {}.", address);
+        Some(create_response(state, StatusCode::Forbidden, None))
+    }
+
+    /// List banned hosts, with the time their bans are lifted.
+    pub fn list_banned(&self, state: State) -> (State, Response) {
+        let banned = self.peers.read().unwrap().bans.banned();
+        let res = create_response(
+            &state,
+            StatusCode::Ok,
+            Some((serde_json::to_vec(&banned).unwrap(), APPLICATION_JSON)),
+        );
+        (state, res)
+    }
+
+    /// Lift the ban of the host of the peer, response NotFound if it's not banned.
+    pub fn unban(&self, mut state: State) -> (State, Response) {
+        let AddressPath { address } = AddressPath::take_from(&mut state);
+        let res = if self.peers.write().unwrap().bans.unban(&address) {
+            info!("unban peer {}.", address);
+            create_response(&state, StatusCode::Ok, None)
+        } else {
+            create_response(&state, StatusCode::NotFound, None)
+        };
+        (state, res)
+    }
+
+    /// Broadcast something to all nodes, banned peers are skipped.
+    pub fn broadcast(&self, handle: Handle, msg: SyncBlockMessage) -> BroadcastFuture {
+        match msg {
+            SyncBlockMessage::PublishBlocks(content) => {
+                self.post_to_peers(&handle, "publish_blocks", content)
+            }
+            SyncBlockMessage::PublishTransaction(content) => {
+                self.post_to_peers(&handle, "publish_tx", content)
+            }
+            // Ignore other message types currently.
+            _ => Box::new(future::ok(None)),
+        }
+    }
+
+    /// Query the peer, response the content.
+    pub fn query(
+        &self,
+        handle: &Handle,
+        address: &str,
+        msg: SyncBlockMessage,
+    ) -> ResponseContentFuture {
+        let path = match msg {
+            SyncBlockMessage::QueryLatest => "response_latest_block",
+            SyncBlockMessage::QueryAll => "response_whole_chain",
+            // Publish messages are broadcasted rather than queried.
+            _ => {
+                return Box::new(future::err(
+                    io::Error::new(ErrorKind::InvalidInput, "not a query message.").into(),
+                ))
+            }
+        };
+        let dst_path = format!("http://{}/{}", address, path);
+        trace!("query : {} ...", dst_path);
+        http_get(handle, &dst_path)
+    }
+
+    /// Post the content to the path of all live peers.
+    fn post_to_peers(&self, handle: &Handle, path: &str, content: Vec<u8>) -> BroadcastFuture {
+        let mut broadcast_futures = Vec::new();
+        for address in self.peer_addresses() {
+            let clone_content = content.clone();
+            let dst_path = format!("http://{}/{}", address, path);
+            let fut = http_post(&handle.clone(), &dst_path, clone_content);
+            trace!("post to : {} ...", dst_path);
+            broadcast_futures.push(fut);
+        }
+        Box::new(future::join_all(broadcast_futures).then(|_| Ok(None)))
+    }
+}
+
+pub type ResponseContentFuture = Box<Future<Item = Vec<u8>, Error = hyper::Error>>;
+pub type BroadcastFuture = Box<Future<Item = Option<()>, Error = hyper::Error>>;
+
+/// Fail the future if it's not resolved in the seconds.
+pub fn with_timeout<T: 'static>(
+    handle: &Handle,
+    fut: Box<Future<Item = T, Error = hyper::Error>>,
+    secs: u64,
+) -> Box<Future<Item = T, Error = hyper::Error>> {
+    let timeout = match Timeout::new(Duration::from_secs(secs), handle) {
+        Ok(timeout) => timeout,
+        Err(err) => return Box::new(future::err(err.into())),
+    };
+    let timeout =
+        timeout.then(|_| Err(io::Error::new(ErrorKind::TimedOut, "request timed out.").into()));
+    Box::new(
+        fut.select(timeout)
+            .map(|(item, _)| item)
+            .map_err(|(err, _)| err),
+    )
+}
+
+/// Check if the request failed by timing out.
+pub fn is_timeout(err: &hyper::Error) -> bool {
+    match *err {
+        hyper::Error::Io(ref err) => err.kind() == ErrorKind::TimedOut,
+        _ => false,
+    }
+}
+
+pub fn http_get(handle: &Handle, url_str: &str) -> ResponseContentFuture {
+    let client = Client::new(handle);
+    let url: Uri = match url_str.parse() {
+        Ok(url) => url,
+        Err(err) => return Box::new(future::err(err.into())),
+    };
+    let f = client.get(url).and_then(|response| {
+        response
+            .body()
+            .concat2()
+            .and_then(|full_body| Ok(full_body.to_vec()))
+    });
+    Box::new(f)
+}
+
+fn http_post(handle: &Handle, url_str: &str, msg: Vec<u8>) -> ResponseContentFuture {
+    let client = Client::new(handle);
+    let url: Uri = match url_str.parse() {
+        Ok(url) => url,
+        Err(err) => return Box::new(future::err(err.into())),
+    };
+    let mut request = Request::new(Method::Post, url);
+    request.headers_mut().set(ContentType::json());
+    request.headers_mut().set(ContentLength(msg.len() as u64));
+    request.set_body(msg);
+    let f = client.request(request).and_then(|response| {
+        response
+            .body()
+            .concat2()
+            .and_then(|full_body| Ok(full_body.to_vec()))
+    });
+    Box::new(f)
+}
+
+#[cfg(test)]
+mod test_peer {
+    use super::*;
+    extern crate env_logger;
+
+    #[test]
+    fn test_compare_and_update() {
+        let _ = env_logger::try_init();
+        let mut self_peers_raw = Peers::new();
+        self_peers_raw
+            .carriers
+            .push(Peer::new("127.0.0.1:9111", Liveness::Live));
+        let self_peers = Arc::new(RwLock::new(self_peers_raw));
+        let mut other_peers = Peers::new();
+        other_peers
+            .carriers
+            .push(Peer::new("127.0.0.1:9888", Liveness::Live));
+        Peers::compare_and_update(self_peers.clone(), other_peers);
+
+        let mut expected_peers = Peers::new();
+        expected_peers
+            .carriers
+            .push(Peer::new("127.0.0.1:9111", Liveness::Live));
+        expected_peers
+            .carriers
+            .push(Peer::new("127.0.0.1:9888", Liveness::Live));
+        let deref_peers = &*self_peers.read().unwrap();
+        trace!("resulted peers : {:?}", deref_peers);
+        assert_eq!(&expected_peers, deref_peers);
+    }
+
+    #[test]
+    fn test_ignore_invalid_and_excess_peers() {
+        for address in ["127.0.0.1:9111", "localhost:9111", "[::1]:9111"].iter() {
+            assert!(is_valid_address(address));
+        }
+        for address in ["127.0.0.1", "127.0.0.1:0", ":9111", "a/b:9111", "[::1:9111"].iter() {
+            assert!(!is_valid_address(address));
+        }
+        let ipv6 = peer_address("::1".parse().unwrap(), 9111).unwrap();
+        assert_eq!("[::1]:9111", ipv6);
+        assert!(is_valid_address(&ipv6));
+        assert!(peer_address("127.0.0.1".parse().unwrap(), 70000).is_none());
+
+        let self_peers = Arc::new(RwLock::new(Peers::new()));
+        let mut other_peers = Peers::from(vec![Peer::new("10.0.0.1/x:9111", Liveness::Unknown)]);
+        for i in 0..MAX_PEERS + 1 {
+            let address = format!("10.0.{}.{}:9111", i / 256, i % 256);
+            other_peers
+                .carriers
+                .push(Peer::new(address, Liveness::Unknown));
+        }
+        Peers::compare_and_update(self_peers.clone(), other_peers);
+        let self_peers = self_peers.read().unwrap();
+        assert_eq!(MAX_PEERS, self_peers.carriers.len());
+        assert_eq!("10.0.0.0:9111", self_peers.carriers[0].address);
+    }
+
+    #[test]
+    fn test_record_handshake() {
+        let handshake = Handshake {
+            protocol_version: handshake::PROTOCOL_VERSION,
+            network_id: "bam-chain".to_string(),
+            genesis_hash: "00ab".to_string(),
+            node_id: "peer".to_string(),
+            listening_address: "0.0.0.0:9111".to_string(),
+            tip_height: 0,
+            capabilities: Vec::new(),
+        };
+        let unreachable = || {
+            Err(HandshakeError::Unreachable(
+                io::Error::new(ErrorKind::TimedOut, "").into(),
+            ))
+        };
+        let mut peers = Peers::new();
+        peers
+            .carriers
+            .push(Peer::new("127.0.0.1:9111", Liveness::Unknown));
+        peers
+            .carriers
+            .push(Peer::new("127.0.0.1:9222", Liveness::Unknown));
+        assert!(peers.live_addresses().is_empty());
+
+        peers.record_handshake("127.0.0.1:9111", &Ok(handshake.clone()), 2);
+        assert_eq!(vec!["127.0.0.1:9111".to_string()], peers.live_addresses());
+        assert!(peers.carriers[0].last_seen.is_some());
+        assert_eq!(Some(handshake.clone()), peers.carriers[0].handshake);
+
+        peers.record_handshake("127.0.0.1:9111", &unreachable(), 2);
+        assert_eq!(Liveness::Failed, peers.carriers[0].liveness);
+        assert_eq!(1, peers.carriers[0].failures);
+        peers.record_handshake("127.0.0.1:9111", &Ok(handshake), 2);
+        assert_eq!(0, peers.carriers[0].failures);
+        peers.record_handshake("127.0.0.1:9111", &unreachable(), 2);
+        peers.record_handshake("127.0.0.1:9111", &unreachable(), 2);
+        assert_eq!(1, peers.carriers.len());
+        peers.record_handshake("127.0.0.1:9222", &unreachable(), 0);
+        assert_eq!(1, peers.carriers.len());
+        // Refusing peers may be banning this node for a while, they are kept until max failures.
+        peers.record_handshake(
+            "127.0.0.1:9222",
+            &Err(HandshakeError::Refused(StatusCode::Forbidden)),
+            3,
+        );
+        assert_eq!(1, peers.carriers.len());
+        assert_eq!(Liveness::Failed, peers.carriers[0].liveness);
+
+        let rejected = Err(HandshakeError::Rejected(
+            "genesis block mismatch.".to_string(),
+        ));
+        peers.record_handshake("127.0.0.1:9222", &rejected, 2);
+        assert!(peers.carriers.is_empty());
+    }
+
+    #[test]
+    fn test_penalize_and_ban() {
+        let self_peers = Arc::new(RwLock::new(Peers::from(vec![
+            Peer::new("8.8.8.8:9111", Liveness::Live),
+            Peer::new("8.8.8.8:9222", Liveness::Live),
+            Peer::new("8.8.4.4:9111", Liveness::Live),
+        ])));
+        {
+            let mut peers = self_peers.write().unwrap();
+            peers.penalize("8.8.8.8:9111", Misbehavior::InvalidBlocks);
+            assert_eq!(3, peers.live_addresses().len());
+            peers.penalize("8.8.8.8:9111", Misbehavior::InvalidBlocks);
+            assert_eq!(vec!["8.8.4.4:9111".to_string()], peers.live_addresses());
+        }
+
+        // Banned hosts are not added back on any port.
+        let other_peers = Peers::from(vec![
+            Peer::new("8.8.8.8:9111", Liveness::Unknown),
+            Peer::new("8.8.8.8:9333", Liveness::Unknown),
+        ]);
+        Peers::compare_and_update(self_peers.clone(), other_peers);
+        assert_eq!(1, self_peers.read().unwrap().carriers.len());
+
+        // Only the misbehaving node of a local host is banned.
+        let mut peers = Peers::from(vec![
+            Peer::new("127.0.0.1:9111", Liveness::Live),
+            Peer::new("127.0.0.1:9222", Liveness::Live),
+        ]);
+        peers.penalize("127.0.0.1:9111", Misbehavior::InvalidBlocks);
+        peers.penalize("127.0.0.1:9111", Misbehavior::InvalidBlocks);
+        assert_eq!(vec!["127.0.0.1:9222".to_string()], peers.live_addresses());
+    }
+}
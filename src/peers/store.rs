@@ -0,0 +1,144 @@
+//! The store module keeps peers on disk, so a node reconnects to known peers after restart.
+//!
+//! Peers are saved with their last seen time and scores after every probing round,
+//! and reloaded unverified until the prober handshakes with them again.
+//! Peers not seen for a long time are evicted, and only the most recently seen ones are kept.
+
+use super::ban::BanList;
+use super::{Liveness, Peer, Peers};
+use chrono::Utc;
+use serde_json;
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+const PEERS_FILE: &str = "peers.json";
+
+/// Maximum number of stored peers, if not configured.
+pub const DEFAULT_MAX_STORED_PEERS: usize = 256;
+
+/// Seconds since a peer is last seen before it's evicted, if not configured.
+pub const DEFAULT_STALE_PEER_AGE: i64 = 7 * 24 * 60 * 60;
+
+#[derive(Serialize, Deserialize)]
+struct StoredPeers {
+    carriers: Vec<Peer>,
+    bans: BanList,
+}
+
+#[derive(Debug)]
+pub struct PeerStore {
+    dir: PathBuf,
+    max_peers: usize,
+    stale_age: i64,
+}
+
+impl PeerStore {
+    pub fn new<P: AsRef<Path>>(dir: P, max_peers: usize, stale_age: i64) -> Self {
+        PeerStore {
+            dir: dir.as_ref().to_path_buf(),
+            max_peers,
+            stale_age,
+        }
+    }
+
+    /// Load stored peers, or no peers if nothing is stored yet.
+    /// Only the last seen time of peers is kept, they're live again once the prober handshakes with them.
+    pub fn load(&self) -> io::Result<Peers> {
+        let file = match File::open(self.dir.join(PEERS_FILE)) {
+            Ok(file) => file,
+            Err(ref err) if err.kind() == ErrorKind::NotFound => return Ok(Peers::new()),
+            Err(err) => return Err(err),
+        };
+        let stored: StoredPeers = serde_json::from_reader(file)
+            .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?;
+        let carriers = self
+            .retain_fresh(stored.carriers)
+            .into_iter()
+            .map(|stored| {
+                let mut peer = Peer::new(stored.address, Liveness::Unknown);
+                peer.last_seen = stored.last_seen;
+                peer
+            })
+            .collect();
+        Ok(Peers {
+            carriers,
+            bans: stored.bans,
+        })
+    }
+
+    /// Save peers, the file is replaced at once so a crash never leaves a partial one.
+    pub fn save(&self, peers: &Peers) -> io::Result<()> {
+        let stored = StoredPeers {
+            carriers: self.retain_fresh(peers.carriers.clone()),
+            bans: peers.bans.clone(),
+        };
+        fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(PEERS_FILE);
+        let temp_path = path.with_extension("json.tmp");
+        let mut temp_file = File::create(&temp_path)?;
+        temp_file.write_all(&serde_json::to_vec(&stored)?)?;
+        temp_file.sync_all()?;
+        fs::rename(temp_path, path)
+    }
+
+    /// Evict stale peers, and keep at most `max_peers` most recently seen ones.
+    /// Peers never seen are kept after seen ones, until they are probed.
+    fn retain_fresh(&self, mut carriers: Vec<Peer>) -> Vec<Peer> {
+        let stale_before = Utc::now().timestamp() - self.stale_age;
+        carriers.retain(|peer| peer.last_seen.map_or(true, |seen| seen >= stale_before));
+        carriers.sort_by(|a, b| b.last_seen.cmp(&a.last_seen));
+        carriers.truncate(self.max_peers);
+        carriers
+    }
+}
+
+#[cfg(test)]
+mod test_store {
+    use super::super::ban::Misbehavior;
+    use super::*;
+    use std::env;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        env::temp_dir().join(format!(
+            "bam-chain-{}-{}",
+            name,
+            Utc::now().timestamp_nanos()
+        ))
+    }
+
+    fn seen_peer(address: &str, last_seen: Option<i64>) -> Peer {
+        let mut peer = Peer::new(address, Liveness::Live);
+        peer.last_seen = last_seen;
+        peer
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = temp_dir("peers");
+        let store = PeerStore::new(&dir, 2, DEFAULT_STALE_PEER_AGE);
+        assert_eq!(Peers::new(), store.load().unwrap());
+
+        let now = Utc::now().timestamp();
+        let mut peers = Peers::from(vec![
+            seen_peer("127.0.0.1:9111", None),
+            seen_peer("127.0.0.1:9222", Some(now - 10)),
+            seen_peer("127.0.0.1:9333", Some(now - DEFAULT_STALE_PEER_AGE - 10)),
+            seen_peer("127.0.0.1:9444", Some(now)),
+        ]);
+        peers.penalize("127.0.0.1:9555", Misbehavior::Timeout);
+        store.save(&peers).unwrap();
+
+        let loaded = store.load().unwrap();
+        let addresses: Vec<&str> = loaded
+            .carriers
+            .iter()
+            .map(|peer| peer.address.as_str())
+            .collect();
+        assert_eq!(vec!["127.0.0.1:9444", "127.0.0.1:9222"], addresses);
+        assert_eq!(Liveness::Unknown, loaded.carriers[0].liveness);
+        assert_eq!(Some(now), loaded.carriers[0].last_seen);
+        assert_eq!(-10, loaded.bans.score("127.0.0.1:9555"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}